@@ -0,0 +1,917 @@
+use anyhow::{Context, Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::diff::unified_diff;
+use crate::dns_proxy::{self, DnsProxyManager, ProxyAnswer, ProxyRules};
+use crate::firewall::{self, FirewallManager};
+use crate::hosts::{HostAction, HostsManager, redirect_hosts, resolve_redirect_ips};
+use crate::ip_ranges::IpRanges;
+use crate::region::{ApplyMode, BlockMode, RegionInfo, get_allowed_regions, get_fallbacks};
+use crate::resolved::{self, ResolvedManager};
+use crate::verify::{Expected, Expectation};
+
+// Everything a backend needs to know about what the user picked
+pub struct ApplyRequest<'a> {
+    pub regions: &'a HashMap<String, RegionInfo>,
+    pub blocked_regions: &'a HashMap<String, RegionInfo>,
+    pub selected: &'a HashSet<String>,
+    pub block_mode: BlockMode,
+    pub merge_unstable: bool,
+    pub merge_with_stable: bool,
+    // DNS proxy answers with the selected server instead of 0.0.0.0
+    pub proxy_redirect: bool,
+}
+
+// What apply would change, without touching the system
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    // One line per change, for people
+    pub changes: Vec<String>,
+    pub files: Vec<FileChange>,
+}
+
+impl Plan {
+    fn extend(&mut self, other: Plan) {
+        self.changes.extend(other.changes);
+        self.files.extend(other.files);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl FileChange {
+    // Empty when the file stays the same
+    pub fn diff(&self) -> String {
+        unified_diff(&self.path, &format!("{} (after apply)", self.path), &self.before, &self.after)
+    }
+}
+
+// State before an apply, so a failed apply can be undone
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    hosts: String,
+    active: Vec<ApplyMode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackendStatus {
+    pub active: bool,
+    pub description: String,
+}
+
+pub trait ApplyBackend {
+    fn mode(&self) -> ApplyMode;
+
+    // Shown in the settings dialog
+    fn label(&self) -> &'static str;
+
+    fn plan(&self, request: &ApplyRequest) -> Result<Plan>;
+
+    fn apply(&self, request: &ApplyRequest) -> Result<()>;
+
+    fn revert(&self) -> Result<()>;
+
+    // What revert would change
+    fn plan_revert(&self) -> Result<Plan> {
+        Ok(Plan {
+            changes: vec![format!("Turn off {}", self.label())],
+            files: Vec::new(),
+        })
+    }
+
+    fn status(&self) -> BackendStatus;
+
+    // How every managed hostname should resolve once this method is applied, checked afterwards
+    // through the system resolver. Methods that don't act on name resolution have none.
+    fn expectations(&self, _request: &ApplyRequest) -> Result<Vec<Expectation>> {
+        Ok(Vec::new())
+    }
+
+    // Whether the method relies on /etc/hosts being consulted
+    fn uses_hosts_file(&self) -> bool {
+        false
+    }
+
+    // Whether applying or reverting goes through pkexec, which needs a polkit agent to ask for
+    // the password
+    fn needs_password(&self) -> bool {
+        true
+    }
+
+    // Shown after a successful apply
+    fn applied_message(&self) -> String {
+        "Please restart the game for changes to take effect.".to_string()
+    }
+
+    // Applying this method takes care of `mode`'s state itself, by building on it or by
+    // replacing it, so `mode` must not be reverted separately
+    fn covers(&self, _mode: ApplyMode) -> bool {
+        false
+    }
+}
+
+// All backends in the order they are offered to the user
+pub struct Backends {
+    backends: Vec<Box<dyn ApplyBackend>>,
+    hosts: HostsManager,
+}
+
+impl Backends {
+    pub fn new(hosts_manager: HostsManager) -> Self {
+        Self {
+            backends: vec![
+                Box::new(HostsBackend { hosts: hosts_manager.clone(), mode: ApplyMode::Gatekeep }),
+                Box::new(HostsBackend { hosts: hosts_manager.clone(), mode: ApplyMode::UniversalRedirect }),
+                Box::new(FirewallBackend { firewall: FirewallManager::new() }),
+                Box::new(DnsProxyBackend { proxy: DnsProxyManager::new() }),
+                Box::new(ResolvedBackend { resolved: ResolvedManager::new() }),
+            ],
+            hosts: hosts_manager,
+        }
+    }
+
+    pub fn all(&self) -> &[Box<dyn ApplyBackend>] {
+        &self.backends
+    }
+
+    pub fn get(&self, mode: ApplyMode) -> &dyn ApplyBackend {
+        self.backends.iter()
+            .find(|backend| backend.mode() == mode)
+            .map(|backend| backend.as_ref())
+            .expect("every apply mode has a backend")
+    }
+
+    // Other methods that are still in effect and get reverted when `mode` is applied
+    fn superseded(&self, mode: ApplyMode) -> impl Iterator<Item = &dyn ApplyBackend> {
+        let backend = self.get(mode);
+        self.backends.iter()
+            .rev()
+            .map(|other| other.as_ref())
+            .filter(move |other| other.mode() != mode && !backend.covers(other.mode()))
+    }
+
+    // Everything apply would change, including reverting the other methods
+    pub fn plan(&self, mode: ApplyMode, request: &ApplyRequest) -> Result<Plan> {
+        let mut plan = self.get(mode).plan(request)?;
+        for other in self.superseded(mode) {
+            if other.status().active {
+                plan.extend(other.plan_revert()?);
+            }
+        }
+        Ok(plan)
+    }
+
+    // The methods that would ask for a password if `mode` were applied now: `mode` itself and
+    // the active methods it reverts
+    pub fn password_prompts(&self, mode: ApplyMode) -> Vec<&dyn ApplyBackend> {
+        let backend = self.get(mode);
+        let reverted = self.superseded(mode).filter(|other| other.status().active);
+        std::iter::once(backend)
+            .chain(reverted)
+            .filter(|backend| backend.needs_password())
+            .collect()
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        Ok(Snapshot {
+            hosts: self.hosts.current_content()?,
+            active: self.backends.iter()
+                .filter(|backend| backend.status().active)
+                .map(|backend| backend.mode())
+                .collect(),
+        })
+    }
+
+    // Applies `mode` and reverts whatever other method is still in effect, so only one is active.
+    // Anything that fails is rolled back, the returned snapshot allows rolling back later when
+    // verification shows the change didn't take effect.
+    pub fn apply(&self, mode: ApplyMode, request: &ApplyRequest) -> Result<Snapshot> {
+        let snapshot = self.snapshot()?;
+
+        let result = self.get(mode).apply(request).and_then(|_| {
+            for other in self.superseded(mode) {
+                if other.status().active {
+                    other.revert()?;
+                }
+            }
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => Ok(snapshot),
+            Err(e) => bail!("{}\n\n{}", e, self.rollback(&snapshot)),
+        }
+    }
+
+    // Undoes an apply as far as possible and explains what was restored
+    pub fn rollback(&self, snapshot: &Snapshot) -> String {
+        let mut notes = Vec::new();
+
+        // Turn off what the apply switched on, later backends first since they may build on earlier ones
+        for backend in self.backends.iter().rev() {
+            if backend.uses_hosts_file() || snapshot.active.contains(&backend.mode()) || !backend.status().active {
+                continue;
+            }
+            match backend.revert() {
+                Ok(()) => notes.push(format!("{} was turned off again.", backend.label())),
+                Err(e) => notes.push(format!("{} could not be turned off again: {}", backend.label(), e)),
+            }
+        }
+
+        match self.hosts.restore(&snapshot.hosts) {
+            Ok(true) => notes.push(format!("{} was restored to how it was before applying.", self.hosts.path())),
+            Ok(false) => notes.push(format!("{} was left unchanged.", self.hosts.path())),
+            Err(e) => notes.push(format!(
+                "{} could not be restored ({}). The previous version was saved as {}.bak.",
+                self.hosts.path(), e, self.hosts.path()
+            )),
+        }
+
+        // Methods that were switched off on the way are not switched back on behind the user's back
+        let switched_off: Vec<&str> = self.backends.iter()
+            .filter(|other| {
+                !other.uses_hosts_file() && snapshot.active.contains(&other.mode()) && !other.status().active
+            })
+            .map(|other| other.label())
+            .collect();
+        if !switched_off.is_empty() {
+            notes.push(format!(
+                "Previously active: {}. Apply it again to turn it back on.",
+                switched_off.join(", ")
+            ));
+        }
+
+        notes.join("\n")
+    }
+
+    // Later backends may build on earlier ones, so tear them down first
+    pub fn revert_all(&self) -> Result<()> {
+        for backend in self.backends.iter().rev() {
+            if backend.status().active {
+                backend.revert()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Gatekeep and Universal Redirect: both write the managed section of /etc/hosts
+pub struct HostsBackend {
+    hosts: HostsManager,
+    mode: ApplyMode,
+}
+
+impl HostsBackend {
+    fn selected_region<'a>(&self, request: &ApplyRequest<'a>) -> Result<&'a str> {
+        if request.selected.len() != 1 {
+            bail!("Please select only one server when using Universal Redirect mode.");
+        }
+        Ok(request.selected.iter().next().unwrap())
+    }
+
+    fn file_change(&self, section: &str) -> Result<FileChange> {
+        Ok(FileChange {
+            path: self.hosts.path().to_string(),
+            before: self.hosts.current_content()?,
+            after: self.hosts.planned_content(section)?,
+        })
+    }
+}
+
+impl ApplyBackend for HostsBackend {
+    fn mode(&self) -> ApplyMode {
+        self.mode
+    }
+
+    fn label(&self) -> &'static str {
+        match self.mode {
+            ApplyMode::UniversalRedirect => "Universal Redirect (deprecated)",
+            _ => "Gatekeep (default)",
+        }
+    }
+
+    fn plan(&self, request: &ApplyRequest) -> Result<Plan> {
+        if self.mode == ApplyMode::UniversalRedirect {
+            let region = self.selected_region(request)?;
+            let ips = resolve_redirect_ips(request.regions, region)?;
+            let section = self.hosts.redirect_plan(request.regions, request.blocked_regions, &ips);
+            return Ok(Plan {
+                changes: vec![format!(
+                    "Point every GameLift endpoint in {} at {} (service {}, ping {})",
+                    self.hosts.path(), region, ips.service, ips.ping
+                )],
+                files: vec![self.file_change(&section.content)?],
+            });
+        }
+
+        let section = self.hosts.gatekeep_plan(
+            request.regions,
+            request.blocked_regions,
+            request.selected,
+            request.block_mode,
+            request.merge_unstable,
+            request.merge_with_stable,
+        )?;
+        let allow = section.regions_with(&HostAction::Allow);
+        let block = section.regions_with(&HostAction::Block);
+
+        let what = match request.block_mode {
+            BlockMode::Both => "service and ping endpoints",
+            BlockMode::OnlyPing => "ping endpoints",
+            BlockMode::OnlyService => "service endpoints",
+        };
+
+        let mut changes = vec![
+            format!("Allow in {}: {}", self.hosts.path(), join_names(&allow)),
+            format!("Block {} in {}: {}", what, self.hosts.path(), join_names(&block)),
+        ];
+        changes.extend(fallback_changes(&section.fallbacks));
+
+        Ok(Plan {
+            changes,
+            files: vec![self.file_change(&section.content)?],
+        })
+    }
+
+    fn apply(&self, request: &ApplyRequest) -> Result<()> {
+        match self.mode {
+            ApplyMode::UniversalRedirect => {
+                let region = self.selected_region(request)?;
+                self.hosts
+                    .apply_universal_redirect(request.regions, request.blocked_regions, region)
+                    .map(|_| ())
+            }
+            _ => self.hosts.apply_gatekeep(
+                request.regions,
+                request.blocked_regions,
+                request.selected,
+                request.block_mode,
+                request.merge_unstable,
+                request.merge_with_stable,
+            ),
+        }
+    }
+
+    fn revert(&self) -> Result<()> {
+        self.hosts.revert()
+    }
+
+    fn expectations(&self, request: &ApplyRequest) -> Result<Vec<Expectation>> {
+        // An alternate hosts file (container, Wine prefix) is never asked by this system's resolver
+        if !self.hosts.is_system_file() {
+            return Ok(Vec::new());
+        }
+
+        match self.mode {
+            ApplyMode::UniversalRedirect => redirect_expectations(request, self.selected_region(request)?),
+            _ => Ok(gatekeep_expectations(request)),
+        }
+    }
+
+    fn uses_hosts_file(&self) -> bool {
+        true
+    }
+
+    fn needs_password(&self) -> bool {
+        !self.hosts.writable_by_user()
+    }
+
+    fn plan_revert(&self) -> Result<Plan> {
+        Ok(Plan {
+            changes: vec![format!("Remove the Make Your Choice section from {}", self.hosts.path())],
+            files: vec![self.file_change("")?],
+        })
+    }
+
+    fn status(&self) -> BackendStatus {
+        let section = self.hosts.has_managed_section()
+            .and_then(|present| Ok((present, self.hosts.has_redirect_section()?)));
+
+        match section {
+            Ok((present, redirect)) => {
+                let active = present && (redirect == (self.mode == ApplyMode::UniversalRedirect));
+                let description = if active {
+                    "The hosts file contains a Make Your Choice section.".to_string()
+                } else {
+                    "No section for this method in the hosts file.".to_string()
+                };
+                BackendStatus { active, description }
+            }
+            Err(e) => BackendStatus {
+                active: false,
+                description: format!("The hosts file could not be read ({:#})", e),
+            },
+        }
+    }
+
+    fn applied_message(&self) -> String {
+        format!(
+            "The hosts file was updated successfully ({:?} mode).\n\nPlease restart the game for changes to take effect.",
+            self.mode
+        )
+    }
+
+    // Both modes own the same section, writing one replaces the other
+    fn covers(&self, mode: ApplyMode) -> bool {
+        matches!(mode, ApplyMode::Gatekeep | ApplyMode::UniversalRedirect)
+    }
+}
+
+pub struct FirewallBackend {
+    firewall: FirewallManager,
+}
+
+impl ApplyBackend for FirewallBackend {
+    fn mode(&self) -> ApplyMode {
+        ApplyMode::Firewall
+    }
+
+    fn label(&self) -> &'static str {
+        "Firewall (nftables)"
+    }
+
+    fn plan(&self, request: &ApplyRequest) -> Result<Plan> {
+        if request.selected.is_empty() {
+            bail!("Please select at least one server to allow.");
+        }
+
+        let ip_ranges = IpRanges::load_required()?;
+        let (names, prefixes) = firewall::blocked_prefixes(
+            request.regions,
+            request.blocked_regions,
+            request.selected,
+            request.merge_unstable,
+            request.merge_with_stable,
+            &ip_ranges,
+        );
+
+        let changes = vec![format!(
+            "Reject traffic to {} IPv4 and {} IPv6 prefixes of: {}",
+            prefixes.ipv4.len(),
+            prefixes.ipv6.len(),
+            join_names(&names)
+        )];
+        Ok(Plan { changes, files: Vec::new() })
+    }
+
+    fn apply(&self, request: &ApplyRequest) -> Result<()> {
+        let ip_ranges = IpRanges::load_required()?;
+        self.firewall
+            .apply(
+                request.regions,
+                request.blocked_regions,
+                request.selected,
+                request.merge_unstable,
+                request.merge_with_stable,
+                &ip_ranges,
+            )
+            .map(|_| ())
+    }
+
+    fn revert(&self) -> Result<()> {
+        self.firewall.revert()
+    }
+
+    fn status(&self) -> BackendStatus {
+        let status = self.firewall.status();
+        BackendStatus {
+            active: self.firewall.is_active(),
+            description: status.describe(),
+        }
+    }
+
+    fn applied_message(&self) -> String {
+        "The firewall rules were installed successfully.\n\nPlease restart the game for changes to take effect.".to_string()
+    }
+}
+
+fn proxy_rules(request: &ApplyRequest) -> Result<ProxyRules> {
+    if request.proxy_redirect {
+        if request.selected.len() != 1 {
+            bail!("Please select only one server when the DNS proxy redirects instead of blocking.");
+        }
+        let region = request.selected.iter().next().unwrap();
+        ProxyRules::redirect(request.regions, request.blocked_regions, region)
+    } else {
+        ProxyRules::gatekeep(
+            request.regions,
+            request.blocked_regions,
+            request.selected,
+            request.block_mode,
+            request.merge_unstable,
+            request.merge_with_stable,
+        )
+    }
+}
+
+fn proxy_expectations(request: &ApplyRequest) -> Result<Vec<Expectation>> {
+    if request.proxy_redirect {
+        match request.selected.iter().next() {
+            Some(region) if request.selected.len() == 1 => redirect_expectations(request, region),
+            _ => bail!("Please select only one server when the DNS proxy redirects instead of blocking."),
+        }
+    } else {
+        Ok(gatekeep_expectations(request))
+    }
+}
+
+// Redirecting follows a single server, fallbacks only apply when blocking
+fn proxy_fallback_changes(request: &ApplyRequest) -> Vec<String> {
+    if request.proxy_redirect {
+        return Vec::new();
+    }
+    let fallbacks = get_fallbacks(
+        request.regions,
+        request.selected,
+        request.merge_unstable,
+        request.merge_with_stable,
+    );
+    fallback_changes(&fallbacks)
+}
+
+// Servers merging allows on top of the selection, so they don't come as a surprise
+fn fallback_changes(fallbacks: &[(String, String)]) -> Vec<String> {
+    fallbacks.iter()
+        .map(|(region, fallback)| format!("Also allow {} as the stable fallback for {}", fallback, region))
+        .collect()
+}
+
+fn plan_proxy_rules(rules: &ProxyRules) -> Vec<String> {
+    let blocked = rules.hosts.values().filter(|answer| **answer == ProxyAnswer::Block).count();
+    let mut targets: Vec<&String> = rules.hosts.values()
+        .filter_map(|answer| match answer {
+            ProxyAnswer::Redirect(target) => Some(target),
+            ProxyAnswer::Block => None,
+        })
+        .collect();
+    let redirected = targets.len();
+    targets.sort();
+    targets.dedup();
+
+    let mut changes = vec![format!("DNS proxy answers {} GameLift hostnames with 0.0.0.0", blocked)];
+    if redirected > 0 {
+        changes.push(format!(
+            "DNS proxy answers {} GameLift hostnames with the addresses of {}",
+            redirected,
+            join_names(&targets)
+        ));
+    }
+    changes
+}
+
+pub struct DnsProxyBackend {
+    proxy: DnsProxyManager,
+}
+
+impl ApplyBackend for DnsProxyBackend {
+    fn mode(&self) -> ApplyMode {
+        ApplyMode::DnsProxy
+    }
+
+    fn label(&self) -> &'static str {
+        "DNS proxy (no hosts file edits)"
+    }
+
+    // Runs as a systemd user service
+    fn needs_password(&self) -> bool {
+        false
+    }
+
+    fn plan(&self, request: &ApplyRequest) -> Result<Plan> {
+        let mut changes = plan_proxy_rules(&proxy_rules(request)?);
+        changes.extend(proxy_fallback_changes(request));
+        Ok(Plan { changes, files: Vec::new() })
+    }
+
+    fn apply(&self, request: &ApplyRequest) -> Result<()> {
+        self.proxy.apply(&proxy_rules(request)?)
+    }
+
+    fn revert(&self) -> Result<()> {
+        self.proxy.revert()
+    }
+
+    // No expectations: the resolver only asks the proxy once the user has pointed it there
+
+    fn status(&self) -> BackendStatus {
+        BackendStatus {
+            active: self.proxy.is_active(),
+            description: self.proxy.describe_status(),
+        }
+    }
+
+    fn applied_message(&self) -> String {
+        format!(
+            "The DNS proxy is running on {}.\n\nMake sure your system resolver sends GameLift lookups to it (see the README), then restart the game.",
+            dns_proxy::LISTEN_ADDR
+        )
+    }
+}
+
+pub struct ResolvedBackend {
+    resolved: ResolvedManager,
+}
+
+impl ApplyBackend for ResolvedBackend {
+    fn mode(&self) -> ApplyMode {
+        ApplyMode::SystemdResolved
+    }
+
+    fn label(&self) -> &'static str {
+        "systemd-resolved (DNS proxy, configured for you)"
+    }
+
+    fn plan(&self, request: &ApplyRequest) -> Result<Plan> {
+        let mut changes = plan_proxy_rules(&proxy_rules(request)?);
+        changes.extend(proxy_fallback_changes(request));
+        changes.push(format!(
+            "Send amazonaws.com and api.aws lookups from systemd-resolved to {}",
+            dns_proxy::LISTEN_ADDR
+        ));
+        changes.push("Other lookups may pass through the DNS proxy too, it forwards them to your normal DNS servers".to_string());
+        Ok(Plan {
+            changes,
+            files: vec![drop_in_change(resolved::build_drop_in())],
+        })
+    }
+
+    fn apply(&self, request: &ApplyRequest) -> Result<()> {
+        self.resolved.apply(&proxy_rules(request)?)
+    }
+
+    // Only removes the drop-in, the DNS proxy backend stops the proxy
+    fn revert(&self) -> Result<()> {
+        self.resolved.revert()
+    }
+
+    fn expectations(&self, request: &ApplyRequest) -> Result<Vec<Expectation>> {
+        proxy_expectations(request)
+    }
+
+    fn plan_revert(&self) -> Result<Plan> {
+        Ok(Plan {
+            changes: vec!["Stop sending lookups from systemd-resolved to the DNS proxy".to_string()],
+            files: vec![drop_in_change(String::new())],
+        })
+    }
+
+    fn status(&self) -> BackendStatus {
+        BackendStatus {
+            active: self.resolved.is_active(),
+            description: self.resolved.describe_status(),
+        }
+    }
+
+    fn applied_message(&self) -> String {
+        format!(
+            "systemd-resolved now sends GameLift lookups to the DNS proxy on {}.\n\nPlease restart the game for changes to take effect.",
+            dns_proxy::LISTEN_ADDR
+        )
+    }
+
+    // Runs on top of the DNS proxy
+    fn covers(&self, mode: ApplyMode) -> bool {
+        mode == ApplyMode::DnsProxy
+    }
+}
+
+// Unselected servers blocked, selected ones (and endpoints the block mode skips) left alone
+fn gatekeep_expectations(request: &ApplyRequest) -> Vec<Expectation> {
+    let allowed = get_allowed_regions(
+        request.regions,
+        request.selected,
+        request.merge_unstable,
+        request.merge_with_stable,
+    );
+    let mut expectations = Vec::new();
+
+    for (region_key, region_info) in request.regions.iter() {
+        for host in &region_info.hosts {
+            let is_ping = host.to_lowercase().contains("ping");
+            let include = match request.block_mode {
+                BlockMode::Both => true,
+                BlockMode::OnlyPing => is_ping,
+                BlockMode::OnlyService => !is_ping,
+            };
+            let expected = if include && !allowed.contains(region_key) {
+                Expected::Blocked
+            } else {
+                Expected::Allowed
+            };
+            expectations.push(Expectation { host: host.clone(), expected });
+        }
+    }
+
+    expectations.extend(blocked_expectations(request));
+    expectations
+}
+
+fn redirect_expectations(request: &ApplyRequest, region: &str) -> Result<Vec<Expectation>> {
+    let region_info = request.regions.get(region)
+        .context("Selected region not found")?;
+    let (service_host, ping_host) = redirect_hosts(region_info);
+    let mut expectations = Vec::new();
+
+    for region_info in request.regions.values() {
+        for host in &region_info.hosts {
+            let target = if host.to_lowercase().contains("ping") { ping_host } else { service_host };
+            let expected = if host.eq_ignore_ascii_case(target) {
+                Expected::Allowed
+            } else {
+                Expected::Redirected { target: target.to_string() }
+            };
+            expectations.push(Expectation { host: host.clone(), expected });
+        }
+    }
+
+    expectations.extend(blocked_expectations(request));
+    Ok(expectations)
+}
+
+fn blocked_expectations(request: &ApplyRequest) -> Vec<Expectation> {
+    request.blocked_regions.values()
+        .flat_map(|region_info| region_info.hosts.iter())
+        .map(|host| Expectation { host: host.clone(), expected: Expected::Blocked })
+        .collect()
+}
+
+fn drop_in_change(after: String) -> FileChange {
+    FileChange {
+        path: resolved::DROP_IN_PATH.to_string(),
+        before: fs::read_to_string(resolved::DROP_IN_PATH).unwrap_or_default(),
+        after,
+    }
+}
+
+fn join_names<S: AsRef<str>>(names: &[S]) -> String {
+    if names.is_empty() {
+        return "none".to_string();
+    }
+    names.iter().map(|name| name.as_ref()).collect::<Vec<_>>().join(", ")
+}
+
+// Golden files for what each method writes. A formatting change shows up as a failing test with
+// a diff; if it's intended, regenerate with UPDATE_GOLDEN=1 cargo test and review the changes.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firewall::{blocked_prefixes, build_ruleset};
+    use crate::hosts::{RedirectIps, plan_gatekeep, plan_redirect};
+    use std::path::PathBuf;
+
+    const DISCORD: &str = "https://discord.example/invite";
+    const MODES: [ApplyMode; 5] = [
+        ApplyMode::Gatekeep,
+        ApplyMode::UniversalRedirect,
+        ApplyMode::Firewall,
+        ApplyMode::DnsProxy,
+        ApplyMode::SystemdResolved,
+    ];
+    const BLOCK_MODES: [BlockMode; 3] = [BlockMode::Both, BlockMode::OnlyPing, BlockMode::OnlyService];
+    const LONDON: &str = "Europe (London)";
+    const TOKYO: &str = "Asia Pacific (Tokyo)";
+    // Selections with merge_unstable and merge_with_stable. Unstable London alone merges the
+    // same either way, next to stable Tokyo only merge_with_stable adds Ireland.
+    const CASES: [(&[&str], bool, bool); 4] = [
+        (&[LONDON], false, false),
+        (&[LONDON], true, false),
+        (&[LONDON, TOKYO], true, false),
+        (&[LONDON, TOKYO], true, true),
+    ];
+
+    // Every combination `mode` is rendered for, Universal Redirect only takes one server
+    fn cases(mode: ApplyMode) -> impl Iterator<Item = (BlockMode, &'static [&'static str], bool, bool)> {
+        BLOCK_MODES.into_iter().flat_map(move |block_mode| {
+            CASES.into_iter()
+                .filter(move |(selection, _, _)| mode != ApplyMode::UniversalRedirect || selection.len() == 1)
+                .map(move |(selection, merge_unstable, merge_with_stable)| (block_mode, selection, merge_unstable, merge_with_stable))
+        })
+    }
+
+    // Each group has one unstable and at most one stable region, so merging is unambiguous
+    fn catalog() -> (HashMap<String, RegionInfo>, HashMap<String, RegionInfo>) {
+        let region = |code: &str, stable: bool| RegionInfo {
+            hosts: vec![
+                format!("gamelift.{}.amazonaws.com", code),
+                format!("gamelift-ping.{}.api.aws", code),
+            ],
+            stable,
+        };
+        let regions = HashMap::from([
+            ("Europe (London)".to_string(), region("eu-west-2", false)),
+            ("Europe (Ireland)".to_string(), region("eu-west-1", true)),
+            ("US East (Ohio)".to_string(), region("us-east-2", false)),
+            ("US East (N. Virginia)".to_string(), region("us-east-1", true)),
+            ("Asia Pacific (Tokyo)".to_string(), region("ap-northeast-1", true)),
+        ]);
+        let blocked = HashMap::from([(
+            "China (Beijing)".to_string(),
+            RegionInfo { hosts: vec!["gamelift.cn-north-1.amazonaws.com.cn".to_string()], stable: false },
+        )]);
+        (regions, blocked)
+    }
+
+    fn ip_ranges() -> IpRanges {
+        // An excerpt in the format AWS publishes, with the AMAZON superset and other services
+        let json = fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ip-ranges.json")).unwrap();
+        IpRanges::parse(&json).unwrap()
+    }
+
+    // What `mode` would write for the fixed catalog with `selection` selected
+    fn render(mode: ApplyMode, block_mode: BlockMode, selection: &[&str], merge_unstable: bool, merge_with_stable: bool) -> String {
+        let (regions, blocked_regions) = catalog();
+        let selected: HashSet<String> = selection.iter().map(|name| name.to_string()).collect();
+        let request = ApplyRequest {
+            regions: &regions,
+            blocked_regions: &blocked_regions,
+            selected: &selected,
+            block_mode,
+            merge_unstable,
+            merge_with_stable,
+            proxy_redirect: false,
+        };
+
+        match mode {
+            ApplyMode::Gatekeep => plan_gatekeep(
+                DISCORD, &regions, &blocked_regions, &selected, block_mode, merge_unstable, merge_with_stable,
+            )
+            .unwrap()
+            .content,
+            ApplyMode::UniversalRedirect => {
+                let ips = RedirectIps { service: "192.0.2.10".to_string(), ping: "192.0.2.11".to_string() };
+                plan_redirect(DISCORD, &regions, &blocked_regions, &ips).content
+            }
+            ApplyMode::Firewall => {
+                let (_, prefixes) = blocked_prefixes(&regions, &blocked_regions, &selected, merge_unstable, merge_with_stable, &ip_ranges());
+                build_ruleset(&prefixes)
+            }
+            ApplyMode::DnsProxy => proxy_rules(&request).unwrap().to_yaml().unwrap(),
+            ApplyMode::SystemdResolved => format!(
+                "{}\n{}",
+                resolved::build_drop_in(),
+                proxy_rules(&request).unwrap().to_yaml().unwrap()
+            ),
+        }
+    }
+
+    fn golden_file(mode: ApplyMode) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{:?}.txt", mode).to_lowercase())
+    }
+
+    #[test]
+    fn generated_content_matches_golden_files() {
+        let mut failures = Vec::new();
+
+        for mode in MODES {
+            let mut actual = String::new();
+            for (block_mode, selection, merge_unstable, merge_with_stable) in cases(mode) {
+                actual.push_str(&format!(
+                    "==== {:?}, {:?}, {}, merge_unstable: {}, merge_with_stable: {} ====\n",
+                    mode, block_mode, selection.join(" + "), merge_unstable, merge_with_stable
+                ));
+                actual.push_str(&render(mode, block_mode, selection, merge_unstable, merge_with_stable));
+                actual.push('\n');
+            }
+
+            let path = golden_file(mode);
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, &actual).unwrap();
+                continue;
+            }
+
+            let expected = fs::read_to_string(&path).unwrap_or_default();
+            let label = path.display().to_string();
+            let diff = unified_diff(&label, &format!("{} (generated)", label), &expected, &actual);
+            if !diff.is_empty() {
+                failures.push(diff);
+            }
+        }
+
+        assert!(
+            failures.is_empty(),
+            "Generated content differs from the golden files, run with UPDATE_GOLDEN=1 if intended:\n{}",
+            failures.join("\n")
+        );
+    }
+
+    #[test]
+    fn generated_content_is_stable_across_runs() {
+        for mode in MODES {
+            for (block_mode, selection, merge_unstable, merge_with_stable) in cases(mode) {
+                let first = render(mode, block_mode, selection, merge_unstable, merge_with_stable);
+                for _ in 0..5 {
+                    assert_eq!(render(mode, block_mode, selection, merge_unstable, merge_with_stable), first);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,600 @@
+use std::collections::HashSet;
+
+use crate::backend::{ApplyRequest, Backends, Snapshot};
+use crate::dns_proxy;
+use crate::game_install;
+use crate::game_session;
+use crate::hosts::{self, HostsManager};
+use crate::ip_ranges::{CacheUpdate, IpRanges};
+use crate::refresh::{self, RefreshOutcome};
+use crate::region::{ApplyMode, get_all_regions, get_blocked_regions, get_region_code, get_selectable_regions};
+use crate::schedule;
+use crate::settings::{ApplyOptions, Profile, UserSettings};
+use crate::verify;
+
+const USAGE: &str = "Usage: make-your-choice [--hosts-file PATH] [COMMAND]
+
+Without a command the graphical interface is started.
+
+Options:
+  --hosts-file PATH
+             Manage PATH instead of /etc/hosts, e.g. in a container rootfs or a Wine prefix
+             (same as setting MAKE_YOUR_CHOICE_HOSTS_FILE). No password is asked if PATH is writable.
+
+Commands:
+  apply [--dry-run] SERVER...
+             Apply the selected servers with the method from the settings, or with --dry-run
+             only show what would change (server names as shown in the app, e.g. \"Europe (London)\")
+  apply [--dry-run] --profile NAME
+             Apply a profile saved in the app with its servers and method, for this once: the
+             settings keep their method
+  profiles   List the saved profiles
+  schedule [install | remove]
+             List the scheduled profile switches with their next run, or install or remove
+             systemd user timers that run them while the app is closed
+  run [--profile NAME] -- COMMAND...
+             Apply the game session profile (or NAME, or else the servers last applied from the
+             app), run COMMAND and restore the previous state when it exits.
+             As a Steam launch option: make-your-choice run -- %command%
+  revert     Remove everything Make Your Choice applied, like \"Revert to Default\"
+  refresh    Re-resolve the Universal Redirect target and update the hosts file if its IPs changed
+  status     Show whether hosts entries, firewall rules or DNS settings are currently applied
+  ip-ranges [update | import FILE]
+             Show the cached AWS IP ranges per region, download the latest ones or import a local ip-ranges.json
+  dns-proxy  Run the local DNS proxy in the foreground (normally started as a systemd user service)
+  help       Show this help message";
+
+// Options given before the command, they apply to the GUI as well
+#[derive(Debug, Clone, Default)]
+pub struct GlobalOptions {
+    pub hosts_path: Option<String>,
+}
+
+impl GlobalOptions {
+    pub fn hosts_manager(&self, discord_url: &str) -> HostsManager {
+        HostsManager::with_path(discord_url.to_string(), hosts::configured_hosts_path(self.hosts_path.as_deref()))
+    }
+}
+
+// Splits the global options off the arguments, Err is the exit code for invalid options
+pub fn parse_options(args: &[String]) -> Result<(GlobalOptions, &[String]), i32> {
+    let mut options = GlobalOptions::default();
+    let mut args = args;
+    while args.first().map(|arg| arg.as_str()) == Some("--hosts-file") {
+        let Some(path) = args.get(1) else {
+            eprintln!("--hosts-file needs a path\n\n{}", USAGE);
+            return Err(2);
+        };
+        options.hosts_path = Some(path.clone());
+        args = &args[2..];
+    }
+    Ok((options, args))
+}
+
+// Returns None when no command was given and the GUI should start instead.
+pub fn run(options: &GlobalOptions, args: &[String]) -> Option<i32> {
+    let command = args.first()?;
+
+    let code = match command.as_str() {
+        "apply" => cmd_apply(options, &args[1..]),
+        "profiles" => cmd_profiles(),
+        "schedule" => cmd_schedule(options, &args[1..]),
+        "run" => cmd_run(options, &args[1..]),
+        "revert" => cmd_revert(options),
+        "refresh" => cmd_refresh(options),
+        "status" => cmd_status(options),
+        "ip-ranges" => cmd_ip_ranges(&args[1..]),
+        "dns-proxy" => cmd_dns_proxy(),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            0
+        }
+        other => {
+            eprintln!("Unknown command: {}\n\n{}", other, USAGE);
+            2
+        }
+    };
+
+    Some(code)
+}
+
+fn cmd_apply(options: &GlobalOptions, args: &[String]) -> i32 {
+    let regions = get_selectable_regions();
+    let mut settings = UserSettings::load().unwrap_or_default();
+
+    let mut dry_run = false;
+    let mut profile_name = None;
+    let mut server_names = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--profile" => match args.next() {
+                Some(name) => profile_name = Some(name),
+                None => {
+                    eprintln!("--profile needs a name\n\n{}", USAGE);
+                    return 2;
+                }
+            },
+            _ => server_names.push(arg.clone()),
+        }
+    }
+
+    // A profile's options are used for this apply only, the saved ones stay
+    let apply_options = match profile_name {
+        Some(name) => {
+            if !server_names.is_empty() {
+                eprintln!("Give either --profile or servers, not both.");
+                return 2;
+            }
+            let Some(profile) = find_profile(&settings, name) else {
+                return 2;
+            };
+            server_names = profile.selected.clone();
+            settings.apply_options(Some(profile))
+        }
+        None => settings.apply_options(None),
+    };
+
+    let mut selected = HashSet::new();
+    for name in &server_names {
+        match regions.keys().find(|key| key.eq_ignore_ascii_case(name)) {
+            Some(key) => {
+                selected.insert(key.clone());
+            }
+            None => {
+                let mut names: Vec<&String> = regions.keys().collect();
+                names.sort();
+                eprintln!("Unknown server: {}\n\nAvailable servers:", name);
+                for name in names {
+                    eprintln!("  {}", name);
+                }
+                return 2;
+            }
+        }
+    }
+
+    let (code, snapshot) = apply_selection(options, &settings, &selected, apply_options, dry_run);
+    if snapshot.is_some() {
+        remember_redirect_region(&mut settings, apply_options.apply_mode, &selected);
+        if profile_name.is_none() {
+            settings.remember_selection(&selected);
+        }
+        let _ = settings.save();
+    }
+    code
+}
+
+fn find_profile<'a>(settings: &'a UserSettings, name: &str) -> Option<&'a Profile> {
+    let profile = settings.profile(name);
+    if profile.is_none() {
+        let names: Vec<&str> = settings.profiles.iter().map(|profile| profile.name.as_str()).collect();
+        if names.is_empty() {
+            eprintln!("Unknown profile: {}\n\nNo profiles are saved yet.", name);
+        } else {
+            eprintln!("Unknown profile: {}\n\nSaved profiles: {}", name, names.join(", "));
+        }
+    }
+    profile
+}
+
+// Same bookkeeping as the GUI, so the refresh job knows what to re-resolve
+fn remember_redirect_region(settings: &mut UserSettings, apply_mode: ApplyMode, selected: &HashSet<String>) {
+    settings.redirect_region = if apply_mode == ApplyMode::UniversalRedirect {
+        selected.iter().next().cloned()
+    } else {
+        None
+    };
+}
+
+// Plans, applies and verifies `selected`. Returns the exit code and, when the change stayed in
+// place, the snapshot of the state before it.
+fn apply_selection(
+    options: &GlobalOptions,
+    settings: &UserSettings,
+    selected: &HashSet<String>,
+    apply_options: ApplyOptions,
+    dry_run: bool,
+) -> (i32, Option<Snapshot>) {
+    let regions = get_selectable_regions();
+    let blocked_regions = get_blocked_regions();
+    let request = ApplyRequest {
+        regions: &regions,
+        blocked_regions: &blocked_regions,
+        selected,
+        block_mode: apply_options.block_mode,
+        merge_unstable: apply_options.merge_unstable,
+        merge_with_stable: settings.merge_with_stable,
+        proxy_redirect: settings.dns_proxy_redirect,
+    };
+
+    let hosts_manager = options.hosts_manager(crate::DISCORD_URL);
+    let backends = Backends::new(hosts_manager.clone());
+    let plan = match backends.plan(apply_options.apply_mode, &request) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return (1, None);
+        }
+    };
+
+    for change in &plan.changes {
+        println!("{}", change);
+    }
+
+    if dry_run {
+        for file in &plan.files {
+            let diff = file.diff();
+            if diff.is_empty() {
+                println!("\n{} stays the same.", file.path);
+            } else {
+                print!("\n{}", diff);
+            }
+        }
+        return (0, None);
+    }
+
+    let backend = backends.get(apply_options.apply_mode);
+    let expectations = match backend.expectations(&request) {
+        Ok(expectations) => expectations,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return (1, None);
+        }
+    };
+
+    let snapshot = match backends.apply(apply_options.apply_mode, &request) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return (1, None);
+        }
+    };
+
+    println!("\n{}", backend.applied_message());
+    print_cache_report(&hosts_manager);
+
+    let mut code = 0;
+    if !expectations.is_empty() {
+        let report = verify::verify(&expectations, backend.uses_hosts_file());
+        println!("\n{}", report.describe());
+
+        if report.contradicted() {
+            eprintln!("\nThe change was rolled back:\n{}", backends.rollback(&snapshot));
+            return (1, None);
+        }
+        if !report.all_ok() {
+            code = 1;
+        }
+    }
+
+    (code, Some(snapshot))
+}
+
+fn cmd_profiles() -> i32 {
+    let settings = UserSettings::load().unwrap_or_default();
+    if settings.profiles.is_empty() {
+        println!("No profiles are saved yet. Save one in the app with the Save button next to the profile list.");
+        return 0;
+    }
+
+    for profile in &settings.profiles {
+        println!("{} ({:?}): {}", profile.name, profile.apply_mode, profile.selected.join(", "));
+    }
+    0
+}
+
+fn cmd_schedule(options: &GlobalOptions, args: &[String]) -> i32 {
+    let settings = UserSettings::load().unwrap_or_default();
+
+    let result = match args.first().map(|s| s.as_str()) {
+        None => return print_schedules(&settings),
+        Some("install") => {
+            // The timers get --hosts-file too, so they apply to the file that was checked here.
+            // They run from another directory, so a relative path is made absolute.
+            let backends = Backends::new(options.hosts_manager(crate::DISCORD_URL));
+            let hosts_path = options.hosts_path.as_deref().map(|path| {
+                std::path::absolute(path)
+                    .map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| path.to_string())
+            });
+            schedule::check_unattended(&settings.schedules, &settings, &backends)
+                .and_then(|_| std::env::current_exe().map_err(anyhow::Error::from))
+                .and_then(|exe| schedule::install_timers(&settings.schedules, &exe, hosts_path.as_deref()))
+        }
+        Some("remove") => schedule::remove_timers(),
+        Some(other) => {
+            eprintln!("Unknown schedule command: {}\n\n{}", other, USAGE);
+            return 2;
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        return 1;
+    }
+
+    // Remembered so the app leaves the switching to the timers
+    let mut settings = settings;
+    settings.schedule_timers = schedule::timers_installed();
+    let _ = settings.save();
+    if settings.schedule_timers {
+        println!("Installed {} systemd user timer(s).", settings.schedules.len());
+    } else {
+        println!("Removed the systemd user timers.");
+    }
+    0
+}
+
+fn print_schedules(settings: &UserSettings) -> i32 {
+    if settings.schedules.is_empty() {
+        println!("No schedules are set up yet. Add one in the app under Options → Schedule.");
+        return 0;
+    }
+
+    let now = chrono::Local::now().naive_local();
+    for entry in &settings.schedules {
+        let next = entry
+            .next_run(now)
+            .map(|run| run.format("%a %Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "never".to_string());
+        let missing = if settings.profile(&entry.profile).is_none() { " (profile missing)" } else { "" };
+        println!("{}{}, next: {}", entry.describe(), missing, next);
+    }
+
+    let runner = if schedule::timers_installed() { "systemd user timers" } else { "the app while it is open" };
+    println!("\nRun by {}.", runner);
+    0
+}
+
+fn cmd_run(options: &GlobalOptions, args: &[String]) -> i32 {
+    let mut settings = UserSettings::load().unwrap_or_default();
+
+    let mut profile = settings.game_session_profile.clone();
+    let mut args = args;
+    while let Some(arg) = args.first() {
+        match arg.as_str() {
+            "--" => {
+                args = &args[1..];
+                break;
+            }
+            "--profile" => match args.get(1) {
+                Some(name) => {
+                    profile = Some(name.clone());
+                    args = &args[2..];
+                }
+                None => {
+                    eprintln!("--profile needs a name\n\n{}", USAGE);
+                    return 2;
+                }
+            },
+            _ => break,
+        }
+    }
+    let command = args;
+    if command.is_empty() {
+        eprintln!("run needs a command to start, e.g. make-your-choice run -- %command%\n\n{}", USAGE);
+        return 2;
+    }
+
+    // Learn the game folder from the launch, for the features that need it
+    if settings.game_path.trim().is_empty() {
+        if let Some(folder) = game_session::game_folder_in(command) {
+            settings.game_launcher = game_install::launcher_for(&folder);
+            settings.game_path = folder.to_string_lossy().to_string();
+            let _ = settings.save();
+        }
+    }
+
+    // The profile's servers and options, otherwise the servers last applied from the app
+    let session = match &profile {
+        Some(name) => find_profile(&settings, name).map(|profile| (profile.selection(), settings.apply_options(Some(profile)))),
+        None if settings.selection.is_empty() => {
+            eprintln!("No profile for game sessions is set and no servers were applied from the app yet. \
+                Pick a profile in Options → Program settings or pass --profile NAME.");
+            None
+        }
+        None => Some((settings.selection.iter().cloned().collect(), settings.apply_options(None))),
+    };
+
+    // A failed apply must not keep the game from starting, it is reported and skipped
+    let redirect_region = settings.redirect_region.clone();
+    let before = match session {
+        Some((selected, apply_options)) => {
+            let (_, snapshot) = apply_selection(options, &settings, &selected, apply_options, false);
+            if snapshot.is_some() {
+                remember_redirect_region(&mut settings, apply_options.apply_mode, &selected);
+                let _ = settings.save();
+            }
+            snapshot
+        }
+        None => None,
+    };
+    if before.is_none() {
+        eprintln!("Starting the game without changes.");
+    }
+
+    let code = match game_session::run_game(command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            127
+        }
+    };
+
+    // Back to what was in place before the session, not to default
+    if let Some(before) = before {
+        let backends = Backends::new(options.hosts_manager(crate::DISCORD_URL));
+        println!("{}", backends.rollback(&before));
+        let mut settings = UserSettings::load().unwrap_or_default();
+        settings.redirect_region = redirect_region;
+        let _ = settings.save();
+    }
+    code
+}
+
+fn cmd_revert(options: &GlobalOptions) -> i32 {
+    let hosts_manager = options.hosts_manager(crate::DISCORD_URL);
+    let backends = Backends::new(hosts_manager.clone());
+    if let Err(e) = backends.revert_all() {
+        eprintln!("Error: {:#}", e);
+        return 1;
+    }
+
+    let mut settings = UserSettings::load().unwrap_or_default();
+    settings.redirect_region = None;
+    let _ = settings.save();
+
+    println!("Reverted to default.");
+    print_cache_report(&hosts_manager);
+    0
+}
+
+fn cmd_refresh(options: &GlobalOptions) -> i32 {
+    let settings = UserSettings::load().unwrap_or_default();
+
+    // Only set while a Universal Redirect is applied, also when a profile applied it
+    let region = match &settings.redirect_region {
+        Some(region) => region.clone(),
+        _ => {
+            println!("Universal Redirect is not in use, nothing to refresh.");
+            return 0;
+        }
+    };
+
+    let hosts_manager = options.hosts_manager(crate::DISCORD_URL);
+    let result = refresh::refresh_universal_redirect(
+        &hosts_manager,
+        &get_selectable_regions(),
+        &get_blocked_regions(),
+        &region,
+    );
+
+    match result {
+        Ok(RefreshOutcome::NotApplied) => {
+            println!("No Universal Redirect section found in the hosts file, nothing to refresh.");
+            0
+        }
+        Ok(RefreshOutcome::Unchanged) => {
+            println!("{}: pinned IPs are still current.", region);
+            0
+        }
+        Ok(RefreshOutcome::Updated { previous, current }) => {
+            println!(
+                "{}: updated service {} -> {}, ping {} -> {}.",
+                region, previous.service, current.service, previous.ping, current.ping
+            );
+            print_cache_report(&hosts_manager);
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            1
+        }
+    }
+}
+
+// Which DNS caches the last hosts file write flushed, if it found any
+fn print_cache_report(hosts_manager: &HostsManager) {
+    if let Some(report) = hosts_manager.take_flush_report() {
+        let report = report.describe();
+        if !report.is_empty() {
+            println!("{}", report);
+        }
+    }
+}
+
+fn cmd_status(options: &GlobalOptions) -> i32 {
+    let settings = UserSettings::load().unwrap_or_default();
+    println!("Method: {:?}", settings.apply_mode);
+
+    let backends = Backends::new(options.hosts_manager(crate::DISCORD_URL));
+    for backend in backends.all() {
+        let status = backend.status();
+        let marker = if status.active { "*" } else { " " };
+        println!("{} {}: {}", marker, backend.label(), status.description.replace("\n\n", " ").replace('\n', " "));
+    }
+    0
+}
+
+fn cmd_dns_proxy() -> i32 {
+    let listen = dns_proxy::LISTEN_ADDR.parse().expect("valid listen address");
+    let result = tokio::runtime::Runtime::new()
+        .map_err(anyhow::Error::from)
+        .and_then(|runtime| runtime.block_on(dns_proxy::serve(listen)));
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            1
+        }
+    }
+}
+
+fn cmd_ip_ranges(args: &[String]) -> i32 {
+    let result = match args.first().map(|s| s.as_str()) {
+        None => return print_ip_ranges(),
+        Some("update") => tokio::runtime::Runtime::new()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(IpRanges::download()))
+            .and_then(|json| IpRanges::store(&json)),
+        Some("import") => match args.get(1) {
+            Some(path) => IpRanges::import(std::path::Path::new(path)),
+            None => {
+                eprintln!("Usage: make-your-choice ip-ranges import FILE");
+                return 2;
+            }
+        },
+        Some(other) => {
+            eprintln!("Unknown ip-ranges command: {}\n\n{}", other, USAGE);
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(CacheUpdate::Updated { sync_token }) => {
+            println!("IP ranges updated (syncToken {}).", sync_token);
+            0
+        }
+        Ok(CacheUpdate::AlreadyCurrent { sync_token }) => {
+            println!("IP ranges are already up to date (syncToken {}).", sync_token);
+            0
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            1
+        }
+    }
+}
+
+fn print_ip_ranges() -> i32 {
+    let ranges = match IpRanges::load_required() {
+        Ok(ranges) => ranges,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            return 1;
+        }
+    };
+
+    println!("Cache: {}", IpRanges::cache_file().display());
+    println!("Published: {} (syncToken {})", ranges.create_date, ranges.sync_token);
+    println!();
+
+    let regions = get_all_regions();
+    let prefixes = ranges.region_prefixes(&regions);
+
+    let mut names: Vec<&String> = regions.keys().collect();
+    names.sort();
+    for name in names {
+        let code = get_region_code(&regions[name]).unwrap_or("?");
+        match prefixes.get(code) {
+            Some(p) if !p.is_empty() => {
+                println!("{:28} {:16} {:4} IPv4 {:4} IPv6", name, code, p.ipv4.len(), p.ipv6.len())
+            }
+            _ => println!("{:28} {:16} no EC2 prefixes published", name, code),
+        }
+    }
+
+    0
+}
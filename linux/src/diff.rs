@@ -0,0 +1,161 @@
+// Minimal unified diff for previewing file changes before anything is written.
+
+const CONTEXT: usize = 3;
+// Above this the line table gets too big, the changed middle is shown as replaced instead
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+// Returns an empty string when both sides are identical.
+pub fn unified_diff(old_label: &str, new_label: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    if ops.iter().all(|(op, _)| *op == Op::Equal) {
+        return String::new();
+    }
+
+    // Line numbers before each op, so hunk headers can be computed from any range
+    let mut old_pos = Vec::with_capacity(ops.len() + 1);
+    let mut new_pos = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for (op, _) in &ops {
+        old_pos.push(o);
+        new_pos.push(n);
+        match op {
+            Op::Equal => {
+                o += 1;
+                n += 1;
+            }
+            Op::Delete => o += 1,
+            Op::Insert => n += 1,
+        }
+    }
+    old_pos.push(o);
+    new_pos.push(n);
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    let changed: Vec<usize> = ops.iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Equal)
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut i = 0;
+    while i < changed.len() {
+        let start = changed[i].saturating_sub(CONTEXT);
+        let mut end = (changed[i] + CONTEXT + 1).min(ops.len());
+        // Changes whose context overlaps end up in the same hunk
+        while i + 1 < changed.len() && changed[i + 1] <= end + CONTEXT {
+            i += 1;
+            end = (changed[i] + CONTEXT + 1).min(ops.len());
+        }
+        i += 1;
+
+        let old_count = old_pos[end] - old_pos[start];
+        let new_count = new_pos[end] - new_pos[start];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_pos[start], old_count),
+            hunk_range(new_pos[start], new_count)
+        ));
+
+        for (op, line) in &ops[start..end] {
+            let prefix = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            out.push(prefix);
+            match line.strip_suffix('\n') {
+                Some(line) => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                None => {
+                    out.push_str(line);
+                    out.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn hunk_range(pos: usize, count: usize) -> String {
+    // An empty range points at the line before it
+    let start = if count == 0 { pos } else { pos + 1 };
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, count)
+    }
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    // Edits to the hosts file are usually one block, so strip what's equal around it first
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old.iter().rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<(Op, &str)> = old[..prefix].iter().map(|line| (Op::Equal, *line)).collect();
+
+    if old_mid.len().saturating_mul(new_mid.len()) > MAX_TABLE_CELLS {
+        ops.extend(old_mid.iter().map(|line| (Op::Delete, *line)));
+        ops.extend(new_mid.iter().map(|line| (Op::Insert, *line)));
+    } else {
+        ops.extend(lcs_ops(old_mid, new_mid));
+    }
+
+    ops.extend(old[old.len() - suffix..].iter().map(|line| (Op::Equal, *line)));
+    ops
+}
+
+fn lcs_ops<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    let width = m + 1;
+    // lengths[i * width + j] is the LCS length of old[i..] and new[j..]
+    let mut lengths = vec![0u32; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            ops.push((Op::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            ops.push((Op::Delete, old[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|line| (Op::Delete, *line)));
+    ops.extend(new[j..].iter().map(|line| (Op::Insert, *line)));
+    ops
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+const OS_RELEASE: &str = "/etc/os-release";
+const OS_RELEASE_FALLBACK: &str = "/usr/lib/os-release";
+
+// Distribution families that ship different default hosts files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    Arch,
+    Debian,
+    Fedora,
+    Suse,
+    Other,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Distro {
+    pub id: String,
+    pub id_like: Vec<String>,
+    pub pretty_name: String,
+}
+
+impl Distro {
+    pub fn detect() -> Self {
+        let content = fs::read_to_string(OS_RELEASE)
+            .or_else(|_| fs::read_to_string(OS_RELEASE_FALLBACK))
+            .unwrap_or_default();
+        Self::parse(&content)
+    }
+
+    pub fn parse(os_release: &str) -> Self {
+        let mut distro = Distro::default();
+        for line in os_release.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').trim_matches('\'');
+            match key.trim() {
+                "ID" => distro.id = value.to_lowercase(),
+                "ID_LIKE" => distro.id_like = value.split_whitespace().map(|s| s.to_lowercase()).collect(),
+                "PRETTY_NAME" => distro.pretty_name = value.to_string(),
+                _ => {}
+            }
+        }
+        distro
+    }
+
+    fn is(&self, id: &str) -> bool {
+        self.id == id || self.id_like.iter().any(|like| like == id)
+    }
+
+    pub fn family(&self) -> Family {
+        if self.is("debian") || self.is("ubuntu") {
+            Family::Debian
+        } else if self.is("fedora") || self.is("rhel") || self.is("centos") {
+            Family::Fedora
+        } else if self.is("suse") || self.id.starts_with("opensuse") {
+            Family::Suse
+        } else if self.is("arch") {
+            Family::Arch
+        } else {
+            Family::Other
+        }
+    }
+
+    pub fn is_steamos(&self) -> bool {
+        self.id == "steamos"
+    }
+
+    pub fn is_nixos(&self) -> bool {
+        self.id == "nixos"
+    }
+
+    pub fn name(&self) -> &str {
+        if self.pretty_name.is_empty() { "Linux" } else { &self.pretty_name }
+    }
+}
+
+pub fn hostname() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
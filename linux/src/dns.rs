@@ -0,0 +1,252 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+// systemd-resolved lists the real upstream servers here. Its stub at 127.0.0.53 also answers
+// from /etc/hosts, which would just hand us back the IPs we pinned ourselves.
+const RESOLVED_UPSTREAM_CONF: &str = "/run/systemd/resolve/resolv.conf";
+const RESOLVED_STUB: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 53);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_AAAA: u16 = 28;
+pub const RCODE_SERVFAIL: u8 = 2;
+
+pub fn upstream_nameservers() -> Vec<SocketAddr> {
+    let mut servers = parse_nameservers(&fs::read_to_string(RESOLV_CONF).unwrap_or_default());
+
+    if servers.iter().any(|s| s.ip() == IpAddr::V4(RESOLVED_STUB)) {
+        let upstream = parse_nameservers(&fs::read_to_string(RESOLVED_UPSTREAM_CONF).unwrap_or_default());
+        if !upstream.is_empty() {
+            servers = upstream;
+        }
+    }
+
+    servers.retain(|s| s.ip() != IpAddr::V4(RESOLVED_STUB));
+    servers
+}
+
+fn parse_nameservers(resolv_conf: &str) -> Vec<SocketAddr> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            if parts.next() != Some("nameserver") {
+                return None;
+            }
+            // Drop IPv6 zone ids (fe80::1%eth0), std can't parse them
+            let addr = parts.next()?.split('%').next()?;
+            addr.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, 53))
+        })
+        .collect()
+}
+
+// Resolves IPv4 addresses straight from the upstream nameservers, bypassing /etc/hosts.
+pub fn resolve_upstream(hostname: &str) -> Result<Vec<Ipv4Addr>> {
+    resolve_via(&upstream_nameservers(), hostname)
+}
+
+pub fn resolve_via(servers: &[SocketAddr], hostname: &str) -> Result<Vec<Ipv4Addr>> {
+    if servers.is_empty() {
+        bail!("No upstream nameservers found in {}", RESOLV_CONF);
+    }
+
+    let mut last_error = None;
+    for &server in servers {
+        match query_a(server, hostname) {
+            Ok(addrs) if !addrs.is_empty() => return Ok(addrs),
+            Ok(_) => last_error = Some(anyhow::anyhow!("No addresses found for {}", hostname)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap())
+}
+
+fn query_a(server: SocketAddr, hostname: &str) -> Result<Vec<Ipv4Addr>> {
+    let bind_addr = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_addr).context("Failed to open UDP socket")?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+
+    let id = query_id();
+    let query = build_query(id, hostname, TYPE_A)?;
+    socket.send_to(&query, server)
+        .with_context(|| format!("Failed to query {}", server))?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf)
+            .with_context(|| format!("No answer from {} for {}", server, hostname))?;
+        // Ignore stray packets from anyone other than the server we asked
+        if from != server {
+            continue;
+        }
+        return parse_a_answers(&buf[..len], id);
+    }
+}
+
+fn query_id() -> u16 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    (nanos ^ std::process::id()) as u16
+}
+
+pub fn build_query(id: u16, hostname: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(32 + hostname.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&0x0100u16.to_be_bytes()); // standard query, recursion desired
+    packet.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    packet.extend_from_slice(&[0, 0, 0, 0, 0, 0]); // ANCOUNT, NSCOUNT, ARCOUNT
+
+    for label in hostname.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Invalid hostname: {}", hostname);
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes()); // class IN
+    Ok(packet)
+}
+
+pub fn parse_a_answers(packet: &[u8], expected_id: u16) -> Result<Vec<Ipv4Addr>> {
+    if packet.len() < 12 {
+        bail!("DNS response too short");
+    }
+    if u16::from_be_bytes([packet[0], packet[1]]) != expected_id {
+        bail!("DNS response id mismatch");
+    }
+
+    let rcode = packet[3] & 0x0f;
+    if rcode != 0 {
+        bail!("DNS server returned error code {}", rcode);
+    }
+
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    let ancount = u16::from_be_bytes([packet[6], packet[7]]);
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        pos = skip_name(packet, pos)? + 4;
+    }
+
+    // CNAMEs come first in the answer section, we only care about the A records at the end
+    let mut addrs = Vec::new();
+    for _ in 0..ancount {
+        pos = skip_name(packet, pos)?;
+        let header = packet.get(pos..pos + 10).context("Truncated DNS answer")?;
+        let rtype = u16::from_be_bytes([header[0], header[1]]);
+        let rdlength = u16::from_be_bytes([header[8], header[9]]) as usize;
+        pos += 10;
+
+        let rdata = packet.get(pos..pos + rdlength).context("Truncated DNS answer")?;
+        if rtype == TYPE_A && rdlength == 4 {
+            addrs.push(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]));
+        }
+        pos += rdlength;
+    }
+
+    Ok(addrs)
+}
+
+// Returns the offset right after the (possibly compressed) name starting at `pos`.
+pub fn skip_name(packet: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *packet.get(pos).context("Truncated DNS name")? as usize;
+        if len & 0xc0 == 0xc0 {
+            // Compression pointer, the name ends here as far as this record is concerned
+            return Ok(pos + 2);
+        }
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        pos += len + 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    // Offset right after the question section
+    pub end: usize,
+}
+
+// Reads the first question of an incoming query. Queries never use name compression.
+pub fn parse_question(packet: &[u8]) -> Result<Question> {
+    if packet.len() < 12 {
+        bail!("DNS query too short");
+    }
+    if u16::from_be_bytes([packet[4], packet[5]]) == 0 {
+        bail!("DNS query without a question");
+    }
+
+    let mut labels = Vec::new();
+    let mut pos = 12;
+    loop {
+        let len = *packet.get(pos).context("Truncated DNS name")? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 != 0 {
+            bail!("Unexpected compressed name in query");
+        }
+        let label = packet.get(pos..pos + len).context("Truncated DNS name")?;
+        labels.push(String::from_utf8_lossy(label).to_lowercase());
+        pos += len;
+    }
+
+    let qtype = packet.get(pos..pos + 2).context("Truncated DNS question")?;
+    let qtype = u16::from_be_bytes([qtype[0], qtype[1]]);
+    packet.get(pos + 2..pos + 4).context("Truncated DNS question")?;
+
+    Ok(Question { name: labels.join("."), qtype, end: pos + 4 })
+}
+
+// Builds an answer to `query` carrying `addrs` (only those matching the question type are used).
+pub fn build_response(query: &[u8], question: &Question, rcode: u8, addrs: &[IpAddr], ttl: u32) -> Vec<u8> {
+    let answers: Vec<&IpAddr> = addrs.iter()
+        .filter(|addr| match addr {
+            IpAddr::V4(_) => question.qtype == TYPE_A,
+            IpAddr::V6(_) => question.qtype == TYPE_AAAA,
+        })
+        .collect();
+
+    let mut packet = Vec::with_capacity(question.end + answers.len() * 28);
+    packet.extend_from_slice(&query[0..2]); // id
+    // QR set, keep opcode and RD from the query, recursion available
+    packet.push(0x80 | (query[2] & 0x79));
+    packet.push(0x80 | (rcode & 0x0f));
+    packet.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    packet.extend_from_slice(&(answers.len() as u16).to_be_bytes()); // ANCOUNT
+    packet.extend_from_slice(&[0, 0, 0, 0]); // NSCOUNT, ARCOUNT
+    packet.extend_from_slice(&query[12..question.end]);
+
+    for addr in answers {
+        packet.extend_from_slice(&[0xc0, 12]); // pointer to the question name
+        packet.extend_from_slice(&question.qtype.to_be_bytes());
+        packet.extend_from_slice(&1u16.to_be_bytes()); // class IN
+        packet.extend_from_slice(&ttl.to_be_bytes());
+        match addr {
+            IpAddr::V4(v4) => {
+                packet.extend_from_slice(&4u16.to_be_bytes());
+                packet.extend_from_slice(&v4.octets());
+            }
+            IpAddr::V6(v6) => {
+                packet.extend_from_slice(&16u16.to_be_bytes());
+                packet.extend_from_slice(&v6.octets());
+            }
+        }
+    }
+
+    packet
+}
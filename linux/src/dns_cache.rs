@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// Local caches that can keep answering with what /etc/hosts said before a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cache {
+    SystemdResolved,
+    Nscd,
+    Dnsmasq,
+    Unbound,
+    Sssd,
+}
+
+const ALL: [Cache; 5] = [Cache::SystemdResolved, Cache::Nscd, Cache::Dnsmasq, Cache::Unbound, Cache::Sssd];
+
+impl Cache {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cache::SystemdResolved => "systemd-resolved",
+            Cache::Nscd => "nscd",
+            Cache::Dnsmasq => "dnsmasq",
+            Cache::Unbound => "unbound",
+            Cache::Sssd => "sssd",
+        }
+    }
+
+    // Process names as they appear in /proc/PID/comm (truncated to 15 characters by the kernel)
+    fn process_names(&self) -> &'static [&'static str] {
+        match self {
+            Cache::SystemdResolved => &["systemd-resolve"],
+            Cache::Nscd => &["nscd"],
+            Cache::Dnsmasq => &["dnsmasq"],
+            Cache::Unbound => &["unbound"],
+            Cache::Sssd => &["sssd", "sssd_nss"],
+        }
+    }
+
+    // Shell command run as root that empties the cache
+    fn flush_command(&self) -> &'static str {
+        match self {
+            Cache::SystemdResolved => "resolvectl flush-caches || systemd-resolve --flush-caches",
+            Cache::Nscd => "nscd -i hosts",
+            // SIGHUP makes dnsmasq drop its cache and re-read /etc/hosts
+            Cache::Dnsmasq => "pkill -HUP -x dnsmasq",
+            Cache::Unbound => "unbound-control flush_zone .",
+            Cache::Sssd => "sss_cache -E",
+        }
+    }
+}
+
+// Looks for running cache daemons, works the same with or without systemd
+pub fn detect() -> Vec<Cache> {
+    detect_in(Path::new("/proc"))
+}
+
+// The cache daemons running according to a procfs mounted at `proc_root`
+fn detect_in(proc_root: &Path) -> Vec<Cache> {
+    let mut running: Vec<String> = Vec::new();
+    if let Ok(entries) = fs::read_dir(proc_root) {
+        for entry in entries.flatten() {
+            let is_pid = entry.file_name().to_string_lossy().chars().all(|c| c.is_ascii_digit());
+            if !is_pid {
+                continue;
+            }
+            if let Ok(comm) = fs::read_to_string(entry.path().join("comm")) {
+                running.push(comm.trim().to_string());
+            }
+        }
+    }
+
+    ALL.into_iter()
+        .filter(|cache| cache.process_names().iter().any(|name| running.iter().any(|comm| comm == name)))
+        .collect()
+}
+
+// Shell snippet that flushes each cache and prints "flushed NAME" or "failed NAME" per cache,
+// meant to run in the same pkexec call as the write so there's only one prompt
+pub fn flush_script(caches: &[Cache]) -> String {
+    caches.iter()
+        .map(|cache| format!(
+            "if ({}) >/dev/null 2>&1; then echo 'flushed {}'; else echo 'failed {}'; fi",
+            cache.flush_command(),
+            cache.name(),
+            cache.name()
+        ))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// Flushes the caches on their own, for writes that didn't need pkexec. Tries without root
+// first and only asks for a password for the caches that refused.
+pub fn flush(caches: &[Cache]) -> FlushReport {
+    let mut report = run_flush_script(Command::new("sh"), caches);
+    if report.failed.is_empty() || unsafe { libc::geteuid() } == 0 {
+        return report;
+    }
+
+    let mut pkexec = Command::new("pkexec");
+    pkexec.arg("sh");
+    let retried = run_flush_script(pkexec, &report.failed);
+    report.flushed.extend(retried.flushed);
+    report.failed = retried.failed;
+    report
+}
+
+// `shell` is sh, possibly behind pkexec. A shell that didn't run counts as failing every cache.
+fn run_flush_script(mut shell: Command, caches: &[Cache]) -> FlushReport {
+    if caches.is_empty() {
+        return FlushReport::default();
+    }
+
+    let output = shell
+        .arg("-c")
+        .arg(flush_script(caches))
+        .stderr(Stdio::null())
+        .output();
+    let stdout = output
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+    FlushReport::parse(caches, &stdout)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlushReport {
+    pub flushed: Vec<Cache>,
+    pub failed: Vec<Cache>,
+}
+
+impl FlushReport {
+    // Reads what flush_script printed. Caches without a line count as failed.
+    pub fn parse(caches: &[Cache], output: &str) -> Self {
+        let mut report = FlushReport::default();
+        for cache in caches {
+            let flushed = output.lines().any(|line| line.trim() == format!("flushed {}", cache.name()));
+            if flushed {
+                report.flushed.push(*cache);
+            } else {
+                report.failed.push(*cache);
+            }
+        }
+        report
+    }
+
+    // Empty when no cache was found
+    pub fn describe(&self) -> String {
+        let names = |caches: &[Cache]| caches.iter().map(|c| c.name()).collect::<Vec<_>>().join(", ");
+
+        let mut text = String::new();
+        if !self.flushed.is_empty() {
+            text.push_str(&format!("Flushed DNS caches: {}.", names(&self.flushed)));
+        }
+        if !self.failed.is_empty() {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&format!(
+                "Could not flush: {}. Old addresses may be served from there until the service is restarted or the entries expire.",
+                names(&self.failed)
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_caches_by_their_process_names() {
+        let root = std::env::temp_dir().join(format!("myc-cache-proc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (pid, comm) in [
+            ("1", "systemd"),
+            // comm is cut to 15 characters
+            ("310", "systemd-resolve"),
+            ("420", "sssd_nss"),
+            ("421", "sssd_nss"),
+            // Not processes, and a name that only contains a cache's name
+            ("self", "dnsmasq"),
+            ("500", "dnsmasq-helper"),
+        ] {
+            fs::create_dir_all(root.join(pid)).unwrap();
+            fs::write(root.join(pid).join("comm"), format!("{}\n", comm)).unwrap();
+        }
+        // A process that exited between listing and reading
+        fs::create_dir_all(root.join("600")).unwrap();
+
+        assert_eq!(detect_in(&root), [Cache::SystemdResolved, Cache::Sssd]);
+        assert!(detect_in(&root.join("missing")).is_empty());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn report_reads_the_flush_script_output() {
+        let caches = [Cache::Nscd, Cache::Dnsmasq, Cache::Unbound];
+        let report = FlushReport::parse(&caches, "flushed nscd\nfailed dnsmasq\n");
+        assert_eq!(report.flushed, [Cache::Nscd]);
+        // No line at all counts as failed too
+        assert_eq!(report.failed, [Cache::Dnsmasq, Cache::Unbound]);
+        assert_eq!(
+            report.describe(),
+            "Flushed DNS caches: nscd. Could not flush: dnsmasq, unbound. Old addresses may be served from there until the service is restarted or the entries expire."
+        );
+        assert_eq!(FlushReport::parse(&[], "").describe(), "");
+    }
+}
@@ -0,0 +1,329 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use crate::dns;
+use crate::hosts::{HostAction, gatekeep_regions, redirect_hosts};
+use crate::region::{BlockMode, RegionInfo};
+use crate::settings::UserSettings;
+use crate::systemd::{systemctl, user_unit_dir};
+
+// Unprivileged port, so the proxy can run as a systemd user service
+pub const LISTEN_ADDR: &str = "127.0.0.1:5354";
+const SERVICE_NAME: &str = "make-your-choice-dns-proxy.service";
+const ANSWER_TTL: u32 = 60;
+const RULES_RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProxyAnswer {
+    // Answer 0.0.0.0 / :: like a hosts file entry would
+    Block,
+    // Answer with the current IPv4 addresses of another hostname
+    Redirect(String),
+}
+
+// Hostnames the proxy answers itself, everything else is forwarded upstream
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyRules {
+    pub hosts: BTreeMap<String, ProxyAnswer>,
+}
+
+impl ProxyRules {
+    pub fn rules_file() -> PathBuf {
+        UserSettings::config_dir().join("dns-proxy.yaml")
+    }
+
+    // A missing rules file means "forward everything"
+    pub fn load() -> Result<Self> {
+        let path = Self::rules_file();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read DNS proxy rules from {:?}", path))?;
+        serde_yaml::from_str(&content)
+            .context("Failed to parse DNS proxy rules")
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = UserSettings::config_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create config directory {:?}", dir))?;
+
+        fs::write(Self::rules_file(), self.to_yaml()?)
+            .context("Failed to write DNS proxy rules")?;
+        Ok(())
+    }
+
+    // Content of the rules file
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self)
+            .context("Failed to serialize DNS proxy rules")
+    }
+
+    // Same semantics as the Gatekeep hosts section
+    pub fn gatekeep(
+        regions: &HashMap<String, RegionInfo>,
+        blocked_regions: &HashMap<String, RegionInfo>,
+        selected: &HashSet<String>,
+        block_mode: BlockMode,
+        merge_unstable: bool,
+        merge_with_stable: bool,
+    ) -> Result<Self> {
+        let mut hosts = BTreeMap::new();
+        let plan = gatekeep_regions(regions, blocked_regions, selected, block_mode, merge_unstable, merge_with_stable)?;
+        for region in plan {
+            for (host, action) in region.hosts {
+                if action == HostAction::Block {
+                    hosts.insert(host.to_lowercase(), ProxyAnswer::Block);
+                }
+            }
+        }
+
+        Ok(Self { hosts })
+    }
+
+    // Same semantics as the Universal Redirect hosts section, but answers follow the target's
+    // current addresses instead of pinning them
+    pub fn redirect(
+        regions: &HashMap<String, RegionInfo>,
+        blocked_regions: &HashMap<String, RegionInfo>,
+        selected_region: &str,
+    ) -> Result<Self> {
+        let region_info = regions.get(selected_region)
+            .context("Selected region not found")?;
+        let (service_host, ping_host) = redirect_hosts(region_info);
+
+        let mut hosts = BTreeMap::new();
+        for region_info in regions.values() {
+            for host in &region_info.hosts {
+                let is_ping = host.to_lowercase().contains("ping");
+                let target = if is_ping { ping_host } else { service_host };
+                // The target itself has to resolve normally
+                if !host.eq_ignore_ascii_case(target) {
+                    hosts.insert(host.to_lowercase(), ProxyAnswer::Redirect(target.to_lowercase()));
+                }
+            }
+        }
+
+        for region_info in blocked_regions.values() {
+            for host in &region_info.hosts {
+                hosts.insert(host.to_lowercase(), ProxyAnswer::Block);
+            }
+        }
+
+        Ok(Self { hosts })
+    }
+}
+
+#[derive(Default)]
+pub struct DnsProxyManager;
+
+impl DnsProxyManager {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn unit_file() -> PathBuf {
+        user_unit_dir().join(SERVICE_NAME)
+    }
+
+    // Writes the user unit pointing at this binary, so it also works for the precompiled binary
+    fn install_unit(&self) -> Result<()> {
+        let exe = std::env::current_exe()
+            .context("Failed to determine the path of this program")?;
+
+        let unit = format!(
+            "[Unit]\n\
+            Description=Make Your Choice: local DNS proxy for GameLift endpoints\n\
+            \n\
+            [Service]\n\
+            ExecStart={} dns-proxy\n\
+            Restart=on-failure\n\
+            \n\
+            [Install]\n\
+            WantedBy=default.target\n",
+            exe.display()
+        );
+
+        let path = Self::unit_file();
+        if fs::read_to_string(&path).ok().as_deref() == Some(unit.as_str()) {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {:?}", parent))?;
+        }
+        fs::write(&path, unit)
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        systemctl(&["daemon-reload"])
+    }
+
+    pub fn apply(&self, rules: &ProxyRules) -> Result<()> {
+        // The running proxy picks up the new rules by itself
+        rules.save()?;
+        self.install_unit()?;
+        systemctl(&["enable", "--now", SERVICE_NAME])
+    }
+
+    pub fn revert(&self) -> Result<()> {
+        if Self::unit_file().exists() {
+            systemctl(&["disable", "--now", SERVICE_NAME])?;
+        }
+        let _ = fs::remove_file(ProxyRules::rules_file());
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        Command::new("systemctl")
+            .args(["--user", "is-active", "--quiet", SERVICE_NAME])
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+
+    pub fn describe_status(&self) -> String {
+        if !self.is_active() {
+            return "The DNS proxy is not running.".to_string();
+        }
+
+        match ProxyRules::load() {
+            Ok(rules) => format!(
+                "The DNS proxy is running on {} and answers {} GameLift hostnames itself.",
+                LISTEN_ADDR,
+                rules.hosts.len()
+            ),
+            Err(e) => format!("The DNS proxy is running on {}, but its rules are unreadable: {:#}", LISTEN_ADDR, e),
+        }
+    }
+}
+
+// Runs the forwarder until the process is stopped. Rules are re-read when the file changes.
+pub async fn serve(listen: SocketAddr) -> Result<()> {
+    let socket = Arc::new(
+        UdpSocket::bind(listen).await
+            .with_context(|| format!("Failed to listen on {}", listen))?,
+    );
+
+    // Never forward to ourselves, resolved lists us among its servers once pointed here
+    let upstreams: Arc<Vec<SocketAddr>> = Arc::new(
+        dns::upstream_nameservers()
+            .into_iter()
+            .filter(|server| server.ip() != listen.ip())
+            .collect(),
+    );
+    if upstreams.is_empty() {
+        bail!("No upstream nameservers to forward to.");
+    }
+
+    let mut rules = Arc::new(ProxyRules::load()?);
+    let mut rules_mtime = rules_modified();
+    let mut last_check = Instant::now();
+
+    let mut buf = [0u8; 4096];
+    loop {
+        let (len, client) = socket.recv_from(&mut buf).await?;
+
+        if last_check.elapsed() >= RULES_RELOAD_INTERVAL {
+            last_check = Instant::now();
+            let mtime = rules_modified();
+            if mtime != rules_mtime {
+                rules_mtime = mtime;
+                // Keep serving the old rules if the new file is broken
+                if let Ok(new_rules) = ProxyRules::load() {
+                    rules = Arc::new(new_rules);
+                }
+            }
+        }
+
+        let query = buf[..len].to_vec();
+        let socket = socket.clone();
+        let rules = rules.clone();
+        let upstreams = upstreams.clone();
+
+        tokio::spawn(async move {
+            if let Some(response) = handle_query(&query, &rules, &upstreams).await {
+                let _ = socket.send_to(&response, client).await;
+            }
+        });
+    }
+}
+
+fn rules_modified() -> Option<SystemTime> {
+    fs::metadata(ProxyRules::rules_file())
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+async fn handle_query(query: &[u8], rules: &ProxyRules, upstreams: &[SocketAddr]) -> Option<Vec<u8>> {
+    let question = match dns::parse_question(query) {
+        Ok(question) => question,
+        Err(_) => return forward(query, upstreams).await,
+    };
+
+    match rules.hosts.get(question.name.trim_end_matches('.')) {
+        Some(ProxyAnswer::Block) => {
+            let unspecified = [IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V6(Ipv6Addr::UNSPECIFIED)];
+            Some(dns::build_response(query, &question, 0, &unspecified, ANSWER_TTL))
+        }
+        Some(ProxyAnswer::Redirect(target)) => {
+            // Universal Redirect is IPv4 only, an empty AAAA answer makes clients fall back to A
+            if question.qtype != dns::TYPE_A {
+                return Some(dns::build_response(query, &question, 0, &[], ANSWER_TTL));
+            }
+
+            let target = target.clone();
+            let servers = upstreams.to_vec();
+            let resolved = tokio::task::spawn_blocking(move || dns::resolve_via(&servers, &target)).await;
+
+            match resolved {
+                Ok(Ok(addrs)) => {
+                    let addrs: Vec<IpAddr> = addrs.into_iter().map(IpAddr::V4).collect();
+                    Some(dns::build_response(query, &question, 0, &addrs, ANSWER_TTL))
+                }
+                _ => Some(dns::build_response(query, &question, dns::RCODE_SERVFAIL, &[], 0)),
+            }
+        }
+        None => forward(query, upstreams).await,
+    }
+}
+
+async fn forward(query: &[u8], upstreams: &[SocketAddr]) -> Option<Vec<u8>> {
+    for upstream in upstreams {
+        let bind_addr = if upstream.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = match UdpSocket::bind(bind_addr).await {
+            Ok(socket) => socket,
+            Err(_) => continue,
+        };
+        if socket.send_to(query, upstream).await.is_err() {
+            continue;
+        }
+
+        let mut buf = vec![0u8; 4096];
+        loop {
+            match timeout(UPSTREAM_TIMEOUT, socket.recv_from(&mut buf)).await {
+                Ok(Ok((len, from))) if from == *upstream => {
+                    buf.truncate(len);
+                    return Some(buf);
+                }
+                // Stray packet from someone else, keep waiting
+                Ok(Ok(_)) => continue,
+                _ => break,
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,294 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::ip_ranges::{IpRanges, RegionPrefixes};
+use crate::region::{RegionInfo, get_allowed_regions, get_region_code};
+use crate::settings::UserSettings;
+
+const TABLE_FAMILY: &str = "inet";
+const TABLE_NAME: &str = "make_your_choice";
+// Exit code of the revert script when there is no table to delete
+const TABLE_MISSING_EXIT: i32 = 3;
+
+// What was last installed, used for status reporting since listing nftables needs root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirewallState {
+    pub blocked_regions: Vec<String>,
+    pub ipv4_prefixes: usize,
+    pub ipv6_prefixes: usize,
+    pub sync_token: String,
+    pub applied_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum FirewallStatus {
+    Inactive,
+    // The table exists and nft could be queried directly
+    Active { ipv4_prefixes: usize, ipv6_prefixes: usize },
+    // nft couldn't be queried without root, this is what we installed last
+    Recorded(FirewallState),
+}
+
+impl FirewallStatus {
+    pub fn describe(&self) -> String {
+        match self {
+            FirewallStatus::Inactive => "No Make Your Choice firewall rules are installed.".to_string(),
+            FirewallStatus::Active { ipv4_prefixes, ipv6_prefixes } => format!(
+                "Firewall rules are active.\n\nBlocked prefixes: {} IPv4, {} IPv6.",
+                ipv4_prefixes, ipv6_prefixes
+            ),
+            FirewallStatus::Recorded(state) => format!(
+                "Firewall rules were installed on {}.\n\nBlocked prefixes: {} IPv4, {} IPv6 (ip-ranges syncToken {}).\nBlocked servers: {}\n\nnftables can't be queried without root, so this is the last applied state.",
+                state.applied_at,
+                state.ipv4_prefixes,
+                state.ipv6_prefixes,
+                state.sync_token,
+                state.blocked_regions.join(", ")
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FirewallManager;
+
+impl FirewallManager {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn state_file() -> PathBuf {
+        UserSettings::config_dir().join("firewall.yaml")
+    }
+
+    fn load_state(&self) -> Option<FirewallState> {
+        let content = fs::read_to_string(Self::state_file()).ok()?;
+        serde_yaml::from_str(&content).ok()
+    }
+
+    fn save_state(&self, state: &FirewallState) -> Result<()> {
+        let dir = UserSettings::config_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create config directory {:?}", dir))?;
+
+        let yaml = serde_yaml::to_string(state)
+            .context("Failed to serialize firewall state")?;
+        fs::write(Self::state_file(), yaml)
+            .context("Failed to write firewall state")?;
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        !matches!(self.status(), FirewallStatus::Inactive)
+    }
+
+    pub fn apply(
+        &self,
+        regions: &HashMap<String, RegionInfo>,
+        blocked_regions: &HashMap<String, RegionInfo>,
+        selected: &HashSet<String>,
+        merge_unstable: bool,
+        merge_with_stable: bool,
+        ip_ranges: &IpRanges,
+    ) -> Result<FirewallState> {
+        if selected.is_empty() {
+            bail!("Please select at least one server to allow.");
+        }
+
+        let (blocked_names, prefixes) = blocked_prefixes(regions, blocked_regions, selected, merge_unstable, merge_with_stable, ip_ranges);
+        if prefixes.is_empty() {
+            bail!("The ip-ranges file doesn't contain any EC2 prefixes for the regions to block.");
+        }
+
+        self.run_nft_script(&build_ruleset(&prefixes))?;
+
+        let state = FirewallState {
+            blocked_regions: blocked_names,
+            ipv4_prefixes: prefixes.ipv4.len(),
+            ipv6_prefixes: prefixes.ipv6.len(),
+            sync_token: ip_ranges.sync_token.clone(),
+            applied_at: chrono::Local::now().to_rfc3339(),
+        };
+        self.save_state(&state)?;
+        Ok(state)
+    }
+
+    pub fn revert(&self) -> Result<()> {
+        ensure_nft_installed()?;
+
+        // nft said the table isn't there, only the recorded state is left over
+        if matches!(list_table(), TableListing::Missing) {
+            let _ = fs::remove_file(Self::state_file());
+            return Ok(());
+        }
+
+        // Listing needs root as well, so the existence check runs on the root side. Deleting a
+        // missing table is an error, a missing table exits with TABLE_MISSING_EXIT instead.
+        let command = format!(
+            "nft list table {0} {1} >/dev/null 2>&1 || exit {2}; nft delete table {0} {1}",
+            TABLE_FAMILY, TABLE_NAME, TABLE_MISSING_EXIT
+        );
+
+        let status = Command::new("pkexec")
+            .arg("sh")
+            .arg("-c")
+            .arg(&command)
+            .status()
+            .context("Failed to execute pkexec")?;
+
+        if !status.success() && status.code() != Some(TABLE_MISSING_EXIT) {
+            bail!("Failed to remove the firewall rules. Operation was cancelled or permission was denied.");
+        }
+
+        // Only forget the rules once they are gone, otherwise the status would hide them
+        let _ = fs::remove_file(Self::state_file());
+        Ok(())
+    }
+
+    pub fn status(&self) -> FirewallStatus {
+        match list_table() {
+            TableListing::Found(listing) => FirewallStatus::Active {
+                ipv4_prefixes: count_set_elements(&listing, "blocked_v4"),
+                ipv6_prefixes: count_set_elements(&listing, "blocked_v6"),
+            },
+            TableListing::Missing => FirewallStatus::Inactive,
+            TableListing::Unknown => match self.load_state() {
+                Some(state) => FirewallStatus::Recorded(state),
+                None => FirewallStatus::Inactive,
+            },
+        }
+    }
+
+    fn run_nft_script(&self, script: &str) -> Result<()> {
+        ensure_nft_installed()?;
+
+        // The ruleset goes to nft over stdin, a file in /tmp could be swapped before root reads it
+        let mut child = Command::new("pkexec")
+            .arg("nft")
+            .arg("-f")
+            .arg("-")
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Failed to execute pkexec")?;
+
+        // Closing stdin ends the script. A cancelled pkexec fails the write too, its status says more.
+        let written = match child.stdin.take() {
+            Some(mut stdin) => stdin.write_all(script.as_bytes()),
+            None => Ok(()),
+        };
+        let status = child.wait().context("Failed to wait for pkexec")?;
+
+        if !status.success() {
+            bail!("Failed to install the firewall rules. Operation was cancelled or permission was denied.");
+        }
+
+        written.context("Failed to pass the firewall rules to nft")
+    }
+}
+
+enum TableListing {
+    Found(String),
+    Missing,
+    // nft couldn't be queried, usually because listing needs root
+    Unknown,
+}
+
+fn list_table() -> TableListing {
+    let output = match Command::new("nft").args(["list", "table", TABLE_FAMILY, TABLE_NAME]).output() {
+        Ok(output) => output,
+        Err(_) => return TableListing::Unknown,
+    };
+
+    if output.status.success() {
+        return TableListing::Found(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    if String::from_utf8_lossy(&output.stderr).contains("No such file or directory") {
+        return TableListing::Missing;
+    }
+    TableListing::Unknown
+}
+
+fn ensure_nft_installed() -> Result<()> {
+    match Command::new("nft").arg("--version").output() {
+        Ok(output) if output.status.success() => Ok(()),
+        _ => bail!("nftables is not installed. Please install the \"nftables\" package to use Firewall mode."),
+    }
+}
+
+// Names of the regions to block, sorted, and their EC2 prefixes in the same order. Fallbacks
+// merged into unstable selections stay reachable, like in the hosts file.
+pub fn blocked_prefixes(
+    regions: &HashMap<String, RegionInfo>,
+    blocked_regions: &HashMap<String, RegionInfo>,
+    selected: &HashSet<String>,
+    merge_unstable: bool,
+    merge_with_stable: bool,
+    ip_ranges: &IpRanges,
+) -> (Vec<String>, RegionPrefixes) {
+    let allowed = get_allowed_regions(regions, selected, merge_unstable, merge_with_stable);
+    let disallowed = regions.iter().filter(|(name, _)| !allowed.contains(*name));
+    let mut blocked: Vec<(&String, &RegionInfo)> = disallowed.chain(blocked_regions.iter()).collect();
+    blocked.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut prefixes = RegionPrefixes::default();
+    for (_, info) in &blocked {
+        if let Some(code) = get_region_code(info) {
+            prefixes.extend(ip_ranges.fleet_prefixes(code));
+        }
+    }
+
+    let names = blocked.into_iter().map(|(name, _)| name.clone()).collect();
+    (names, prefixes)
+}
+
+pub fn build_ruleset(prefixes: &RegionPrefixes) -> String {
+    let mut script = String::new();
+
+    // Declaring the table before deleting it makes the script work whether it exists or not
+    script.push_str(&format!("table {} {}\n", TABLE_FAMILY, TABLE_NAME));
+    script.push_str(&format!("delete table {} {}\n", TABLE_FAMILY, TABLE_NAME));
+    script.push('\n');
+
+    script.push_str(&format!("table {} {} {{\n", TABLE_FAMILY, TABLE_NAME));
+    push_set(&mut script, "blocked_v4", "ipv4_addr", &prefixes.ipv4);
+    push_set(&mut script, "blocked_v6", "ipv6_addr", &prefixes.ipv6);
+    script.push_str("    chain output {\n");
+    script.push_str("        type filter hook output priority 0; policy accept;\n");
+    script.push_str("        ip daddr @blocked_v4 reject\n");
+    script.push_str("        ip6 daddr @blocked_v6 reject\n");
+    script.push_str("    }\n");
+    script.push_str("}\n");
+
+    script
+}
+
+fn push_set(script: &mut String, name: &str, addr_type: &str, elements: &[String]) {
+    script.push_str(&format!("    set {} {{\n", name));
+    script.push_str(&format!("        type {}\n", addr_type));
+    // AWS publishes overlapping prefixes, auto-merge keeps nft from rejecting them
+    script.push_str("        flags interval\n");
+    script.push_str("        auto-merge\n");
+    if !elements.is_empty() {
+        script.push_str(&format!("        elements = {{ {} }}\n", elements.join(", ")));
+    }
+    script.push_str("    }\n");
+}
+
+fn count_set_elements(listing: &str, set_name: &str) -> usize {
+    let start = match listing.find(&format!("set {} {{", set_name)) {
+        Some(pos) => pos,
+        None => return 0,
+    };
+    let set_body = &listing[start..];
+    let end = set_body.find('}').unwrap_or(set_body.len());
+
+    match set_body[..end].find("elements = {") {
+        Some(pos) => set_body[pos..end].split(',').count(),
+        None => 0,
+    }
+}
@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::steam;
+
+// The store or launcher a game folder was installed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Launcher {
+    Steam,
+    SteamFlatpak,
+    SteamSnap,
+    // Epic Games Store through Heroic (native or Flatpak)
+    Heroic,
+    Lutris,
+    // Picked by hand and not found in any launcher's configuration
+    #[default]
+    Other,
+}
+
+impl Launcher {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Launcher::Steam => "Steam",
+            Launcher::SteamFlatpak => "Steam (Flatpak)",
+            Launcher::SteamSnap => "Steam (Snap)",
+            Launcher::Heroic => "Epic Games via Heroic",
+            Launcher::Lutris => "Lutris",
+            Launcher::Other => "Unknown launcher",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameInstall {
+    pub path: PathBuf,
+    pub launcher: Launcher,
+}
+
+// Heroic's copy of legendary's list of installed Epic games
+const HEROIC_INSTALLED: &[&str] = &[
+    ".config/heroic/legendaryConfig/legendary/installed.json",
+    ".var/app/com.heroicgameslauncher.hgl/config/heroic/legendaryConfig/legendary/installed.json",
+];
+
+// One YAML file per game added to Lutris
+const LUTRIS_GAME_DIRS: &[&str] = &[
+    ".config/lutris/games",
+    ".local/share/lutris/games",
+    ".var/app/net.lutris.Lutris/config/lutris/games",
+    ".var/app/net.lutris.Lutris/data/lutris/games",
+];
+
+// Where the Epic and Steam clients put the game inside a Wine prefix
+const PREFIX_GAME_DIRS: &[&str] = &[
+    "drive_c/Program Files/Epic Games/DeadByDaylight",
+    "drive_c/Program Files (x86)/Epic Games/DeadByDaylight",
+    "drive_c/Program Files (x86)/Steam/steamapps/common/Dead by Daylight",
+    "drive_c/Program Files/Steam/steamapps/common/Dead by Daylight",
+];
+
+// Recognizes the game by what is inside the folder, its name differs between stores
+// ("Dead by Daylight" on Steam, "DeadByDaylight" on Epic) and can be anything in Lutris
+pub fn is_valid_game_folder(path: &Path) -> bool {
+    path.join("DeadByDaylight").join("Content").is_dir() && path.join("EasyAntiCheat").is_dir()
+}
+
+// The game folder `path` is in or below, e.g. for an executable in DeadByDaylight/Binaries/Win64
+pub fn game_folder_of(path: &Path) -> Option<PathBuf> {
+    path.ancestors().find(|path| is_valid_game_folder(path)).map(Path::to_path_buf)
+}
+
+fn heroic_installs(home: &Path) -> Vec<GameInstall> {
+    let mut installs = Vec::new();
+    for file in HEROIC_INSTALLED {
+        let Ok(text) = fs::read_to_string(home.join(file)) else {
+            continue;
+        };
+        let Ok(games) = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&text) else {
+            continue;
+        };
+        for game in games.values() {
+            let Some(path) = game.get("install_path").and_then(|path| path.as_str()) else {
+                continue;
+            };
+            let path = PathBuf::from(path);
+            if is_valid_game_folder(&path) {
+                installs.push(GameInstall { path, launcher: Launcher::Heroic });
+            }
+        }
+    }
+    installs
+}
+
+// The game folder a Lutris game configuration points to, through its executable, working
+// directory or the usual install locations inside its Wine prefix
+fn lutris_game_folder(config: &serde_yaml::Value) -> Option<PathBuf> {
+    let game = config.get("game")?;
+    let setting = |key: &str| game.get(key).and_then(|value| value.as_str()).map(PathBuf::from);
+
+    let from_paths = ["exe", "working_dir"]
+        .iter()
+        .filter_map(|key| setting(key))
+        .find_map(|path| game_folder_of(&path));
+    if from_paths.is_some() {
+        return from_paths;
+    }
+
+    let prefix = setting("prefix")?;
+    PREFIX_GAME_DIRS
+        .iter()
+        .map(|dir| prefix.join(dir))
+        .find(|path| is_valid_game_folder(path))
+}
+
+fn lutris_installs(home: &Path) -> Vec<GameInstall> {
+    let mut installs = Vec::new();
+    for dir in LUTRIS_GAME_DIRS {
+        let Ok(entries) = fs::read_dir(home.join(dir)) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "yml"))
+            .collect();
+        files.sort();
+
+        for file in files {
+            let Some(config) = fs::read_to_string(&file)
+                .ok()
+                .and_then(|text| serde_yaml::from_str::<serde_yaml::Value>(&text).ok())
+            else {
+                continue;
+            };
+            if let Some(path) = lutris_game_folder(&config) {
+                installs.push(GameInstall { path, launcher: Launcher::Lutris });
+            }
+        }
+    }
+    installs
+}
+
+// Every install found in the Steam libraries and the Heroic and Lutris configurations under
+// `home`. A folder several launchers know about (Lutris running the Steam copy) is listed once.
+pub fn find_game_installs_in(home: &Path) -> Vec<GameInstall> {
+    let mut installs: Vec<GameInstall> = Vec::new();
+    let mut seen = Vec::new();
+
+    for install in steam::find_installs(home).into_iter().chain(heroic_installs(home)).chain(lutris_installs(home)) {
+        let canonical = install.path.canonicalize().unwrap_or_else(|_| install.path.clone());
+        if !seen.contains(&canonical) {
+            seen.push(canonical);
+            installs.push(install);
+        }
+    }
+
+    installs
+}
+
+pub fn find_game_installs() -> Vec<GameInstall> {
+    match dirs::home_dir() {
+        Some(home) => find_game_installs_in(&home),
+        None => Vec::new(),
+    }
+}
+
+// Which launcher installed the folder, Other if no launcher's configuration mentions it
+pub fn launcher_for(path: &Path) -> Launcher {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    find_game_installs()
+        .into_iter()
+        .find(|install| install.path.canonicalize().unwrap_or_else(|_| install.path.clone()) == canonical)
+        .map(|install| install.launcher)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_folder(path: &Path) {
+        fs::create_dir_all(path.join("DeadByDaylight/Content/Movies")).unwrap();
+        fs::create_dir_all(path.join("DeadByDaylight/Binaries/Win64")).unwrap();
+        fs::create_dir_all(path.join("EasyAntiCheat")).unwrap();
+    }
+
+    #[test]
+    fn validates_by_contents_instead_of_name() {
+        let root = std::env::temp_dir().join(format!("myc-install-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let epic = root.join("DeadByDaylight");
+        game_folder(&epic);
+        assert!(is_valid_game_folder(&epic));
+        assert_eq!(game_folder_of(&epic.join("DeadByDaylight/Binaries/Win64")), Some(epic.clone()));
+
+        // Named right but empty
+        let empty = root.join("Dead by Daylight");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(!is_valid_game_folder(&empty));
+        assert_eq!(game_folder_of(&empty), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn finds_heroic_and_lutris_installs() {
+        let home = std::env::temp_dir().join(format!("myc-launchers-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+
+        let heroic_game = home.join("Games/Heroic/DeadByDaylight");
+        game_folder(&heroic_game);
+        let heroic_config = home.join(HEROIC_INSTALLED[1]);
+        fs::create_dir_all(heroic_config.parent().unwrap()).unwrap();
+        fs::write(
+            &heroic_config,
+            format!(
+                r#"{{"Brill": {{"title": "Dead by Daylight", "install_path": "{}"}}, "Other": {{"title": "Other", "install_path": "/nowhere"}}}}"#,
+                heroic_game.display()
+            ),
+        )
+        .unwrap();
+
+        // One Lutris game points at its executable, the other only names its prefix
+        let prefix = home.join("Games/epic-store");
+        let prefix_game = prefix.join(PREFIX_GAME_DIRS[0]);
+        game_folder(&prefix_game);
+        let lutris_dir = home.join(LUTRIS_GAME_DIRS[0]);
+        fs::create_dir_all(&lutris_dir).unwrap();
+        fs::write(
+            lutris_dir.join("dead-by-daylight-1.yml"),
+            format!("game:\n  exe: {}\n", heroic_game.join("DeadByDaylight/Binaries/Win64/DeadByDaylight-Win64-Shipping.exe").display()),
+        )
+        .unwrap();
+        fs::write(lutris_dir.join("epic-games-store-2.yml"), format!("game:\n  prefix: {}\n", prefix.display())).unwrap();
+        fs::write(lutris_dir.join("broken.yml"), "game: [").unwrap();
+
+        assert_eq!(
+            find_game_installs_in(&home),
+            vec![
+                GameInstall { path: heroic_game, launcher: Launcher::Heroic },
+                GameInstall { path: prefix_game, launcher: Launcher::Lutris },
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&home);
+    }
+}
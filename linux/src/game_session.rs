@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::game_install::game_folder_of;
+
+// Native builds and the Proton/Wine executable (DeadByDaylight-Win64-Shipping.exe) share this prefix
+const GAME_PROCESS: &str = "DeadByDaylight";
+
+// How often the app looks for the game process
+pub const POLL_INTERVAL_SECS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEvent {
+    Started,
+    Exited,
+}
+
+// Remembers whether the game was running at the last poll and reports the transitions
+#[derive(Debug, Default)]
+pub struct GameWatcher {
+    running: bool,
+}
+
+impl GameWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn poll(&mut self) -> Option<GameEvent> {
+        self.update(find_game_process(Path::new("/proc")).is_some())
+    }
+
+    fn update(&mut self, running: bool) -> Option<GameEvent> {
+        if running == self.running {
+            return None;
+        }
+        self.running = running;
+        Some(if running { GameEvent::Started } else { GameEvent::Exited })
+    }
+}
+
+// The pid of a running game process, looked up in a procfs mounted at `proc_root`
+pub fn find_game_process(proc_root: &Path) -> Option<u32> {
+    let own_pid = std::process::id();
+    fs::read_dir(proc_root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            Some((pid, entry.path()))
+        })
+        .filter(|(pid, _)| *pid != own_pid)
+        .find(|(_, dir)| {
+            // Processes can exit between listing and reading, unreadable ones are skipped
+            let comm = fs::read_to_string(dir.join("comm")).unwrap_or_default();
+            let cmdline = fs::read(dir.join("cmdline")).unwrap_or_default();
+            is_game_process(comm.trim_end(), &cmdline)
+        })
+        .map(|(pid, _)| pid)
+}
+
+// comm is cut to 15 characters, so the executable in argv[0] is checked as well. Only argv[0]
+// counts: Proton and launchers carry the game's path in their arguments too.
+fn is_game_process(comm: &str, cmdline: &[u8]) -> bool {
+    if comm.starts_with(GAME_PROCESS) {
+        return true;
+    }
+
+    let argv0 = cmdline.split(|byte| *byte == 0).next().unwrap_or_default();
+    let argv0 = String::from_utf8_lossy(argv0);
+    // Wine shows Windows paths (Z:\...\DeadByDaylight-Win64-Shipping.exe)
+    let executable = argv0.rsplit(['/', '\\']).next().unwrap_or_default();
+    executable.starts_with(GAME_PROCESS)
+}
+
+// The game folder a launch command points into, e.g. Steam's %command% for Proton contains
+// .../steamapps/common/Dead by Daylight/DeadByDaylight.exe
+pub fn game_folder_in(command: &[String]) -> Option<PathBuf> {
+    command
+        .iter()
+        .find_map(|arg| game_folder_of(Path::new(arg)))
+}
+
+// Pid of the game started by run_game, signals to the wrapper are passed on to it
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_signal(signal: libc::c_int) {
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    if pid > 0 {
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+// Runs the launch command and waits for it, returning its exit code. Stopping the wrapper
+// (Steam's "Stop" button, Ctrl+C) stops the game instead of the wrapper, so the caller always
+// gets to clean up afterwards, also when the game crashed.
+pub fn run_game(command: &[String]) -> Result<i32> {
+    let (program, args) = command.split_first().context("No command to run")?;
+
+    // Held back until the game's pid is known, so a stop right at launch reaches the game
+    // instead of ending the wrapper. The game gets the original mask back before it starts.
+    let signals = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+    let mut previous_mask: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        let mut mask: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        for signal in signals {
+            libc::sigaddset(&mut mask, signal);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &mask, &mut previous_mask);
+    }
+
+    let spawned = unsafe {
+        Command::new(program)
+            .args(args)
+            .pre_exec(move || {
+                libc::pthread_sigmask(libc::SIG_SETMASK, &previous_mask, std::ptr::null_mut());
+                Ok(())
+            })
+            .spawn()
+    };
+
+    if let Ok(child) = &spawned {
+        CHILD_PID.store(child.id() as i32, Ordering::SeqCst);
+        let handler = forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in signals {
+            unsafe {
+                libc::signal(signal, handler);
+            }
+        }
+    }
+    // Signals that came in meanwhile are delivered now, to the handler or with their old effect
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous_mask, std::ptr::null_mut());
+    }
+
+    let mut child = spawned.with_context(|| format!("Failed to start {}", program))?;
+    let status = child.wait().context("Failed to wait for the game")?;
+    CHILD_PID.store(0, Ordering::SeqCst);
+
+    // Like a shell: 128 + signal number when the game was killed or crashed
+    Ok(status.code().or_else(|| status.signal().map(|signal| 128 + signal)).unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_native_and_proton_processes() {
+        assert!(is_game_process("DeadByDaylight-", b""));
+        assert!(is_game_process(
+            "wine64-preloade",
+            b"Z:\\home\\me\\.steam\\steamapps\\common\\Dead by Daylight\\DeadByDaylight\\Binaries\\Win64\\DeadByDaylight-Win64-Shipping.exe\0-eac\0"
+        ));
+        assert!(is_game_process("DeadByDaylight", b"/opt/dbd/DeadByDaylight\0"));
+    }
+
+    #[test]
+    fn ignores_launchers_mentioning_the_game() {
+        assert!(!is_game_process(
+            "proton",
+            b"/usr/bin/python3\0/home/me/.steam/steamapps/common/Proton/proton\0waitforexitandrun\0/games/Dead by Daylight/DeadByDaylight.exe\0"
+        ));
+        assert!(!is_game_process("steam", b"/usr/bin/steam\0steam://rungameid/381210\0"));
+    }
+
+    #[test]
+    fn finds_the_game_folder_in_a_launch_command() {
+        let common = std::env::temp_dir().join(format!("myc-launch-{}", std::process::id()));
+        let game = common.join("Dead by Daylight");
+        fs::create_dir_all(game.join("DeadByDaylight/Content")).unwrap();
+        fs::create_dir_all(game.join("EasyAntiCheat")).unwrap();
+
+        let command: Vec<String> = vec![
+            common.join("Proton 9.0/proton").to_string_lossy().to_string(),
+            "waitforexitandrun".to_string(),
+            game.join("DeadByDaylight.exe").to_string_lossy().to_string(),
+        ];
+        assert_eq!(game_folder_in(&command), Some(game));
+        assert_eq!(game_folder_in(&command[..2]), None);
+
+        let _ = fs::remove_dir_all(&common);
+    }
+
+    #[test]
+    fn reports_only_transitions() {
+        let mut watcher = GameWatcher::new();
+        assert_eq!(watcher.update(false), None);
+        assert_eq!(watcher.update(true), Some(GameEvent::Started));
+        assert_eq!(watcher.update(true), None);
+        assert_eq!(watcher.update(false), Some(GameEvent::Exited));
+    }
+
+    #[test]
+    fn finds_the_game_in_a_proc_tree() {
+        let root = std::env::temp_dir().join(format!("myc-proc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (pid, comm, cmdline) in [
+            ("1", "systemd", "/sbin/init\0"),
+            ("4242", "wine64-preloade", "Z:\\games\\DeadByDaylight-Win64-Shipping.exe\0"),
+        ] {
+            fs::create_dir_all(root.join(pid)).unwrap();
+            fs::write(root.join(pid).join("comm"), format!("{}\n", comm)).unwrap();
+            fs::write(root.join(pid).join("cmdline"), cmdline).unwrap();
+        }
+        fs::create_dir_all(root.join("self")).unwrap();
+
+        assert_eq!(find_game_process(&root), Some(4242));
+        fs::remove_dir_all(root.join("4242")).unwrap();
+        assert_eq!(find_game_process(&root), None);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -1,94 +1,262 @@
 use anyhow::{Context, Result, bail};
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::process::Command;
-use crate::region::{BlockMode, RegionInfo, get_group_name};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use crate::distro::{self, Distro, Family};
+use crate::dns;
+use crate::dns_cache::{self, FlushReport};
+use crate::hosts_access::{self, Obstacle};
+use crate::region::{BlockMode, RegionInfo, get_allowed_regions, get_fallbacks};
+use crate::settings::UserSettings;
 
 const SECTION_MARKER: &str = "# --+ Make Your Choice +--";
-const HOSTS_PATH: &str = "/etc/hosts";
+pub const HOSTS_PATH: &str = "/etc/hosts";
+// Overrides the hosts file path, the --hosts-file command line option overrides this in turn
+pub const HOSTS_PATH_ENV: &str = "MAKE_YOUR_CHOICE_HOSTS_FILE";
+const REDIRECT_HEADER: &str = "# Universal Redirect mode: redirect all GameLift endpoints to selected region";
 
+// The two addresses every GameLift endpoint is pointed at in Universal Redirect mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectIps {
+    pub service: String,
+    pub ping: String,
+}
+
+#[derive(Clone)]
 pub struct HostsManager {
     discord_url: String,
+    path: String,
+    // Where checksums and backups of the managed file are kept
+    state_dir: PathBuf,
+    // Which DNS caches the last write flushed, shared between clones
+    last_flush: Arc<Mutex<Option<FlushReport>>>,
+}
+
+// The hosts file to manage: the --hosts-file option wins over the environment variable, which
+// wins over the settings, /etc/hosts is the default
+pub fn configured_hosts_path(option: Option<&str>) -> String {
+    option
+        .map(|path| path.to_string())
+        .or_else(|| std::env::var(HOSTS_PATH_ENV).ok())
+        .or_else(|| UserSettings::load().ok().and_then(|settings| settings.hosts_path))
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(|| HOSTS_PATH.to_string())
 }
 
 impl HostsManager {
     pub fn new(discord_url: String) -> Self {
-        Self { discord_url }
+        Self::with_path(discord_url, configured_hosts_path(None))
+    }
+
+    // Manages another file than the system hosts file, e.g. in a container rootfs or a Wine prefix
+    pub fn with_path(discord_url: String, path: impl Into<String>) -> Self {
+        Self {
+            discord_url,
+            path: path.into(),
+            state_dir: UserSettings::config_dir(),
+            last_flush: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Keeps checksums and backups somewhere else than the config directory
+    #[cfg(test)]
+    fn with_state_dir(mut self, state_dir: PathBuf) -> Self {
+        self.state_dir = state_dir;
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Only the system hosts file is consulted by the resolver and held in DNS caches
+    pub fn is_system_file(&self) -> bool {
+        Path::new(&self.path) == Path::new(HOSTS_PATH)
     }
 
     fn read_hosts(&self) -> Result<String> {
-        fs::read_to_string(HOSTS_PATH)
+        fs::read_to_string(&self.path)
             .or_else(|_| Ok(String::new()))
     }
 
     fn write_hosts(&self, content: &str) -> Result<()> {
+        if let Some(obstacle) = self.obstacle() {
+            let explanation = obstacle.explanation(&self.path, &Distro::detect(), hosts_access::alternative());
+            bail!("{}\n\n{}", obstacle.title(), explanation);
+        }
+
+        if self.writable_by_user() {
+            let _ = fs::copy(&self.path, format!("{}.bak", self.path));
+            fs::write(&self.path, content)
+                .with_context(|| format!("Failed to write to {}", self.path))?;
+            if self.is_system_file() {
+                let report = dns_cache::flush(&dns_cache::detect());
+                *self.last_flush.lock().unwrap() = Some(report);
+            }
+        } else {
+            self.write_privileged(content)?;
+        }
+
+        // pkexec can succeed while the copy didn't land, e.g. when something rewrites the file
+        if self.read_hosts()? != content {
+            bail!("{} doesn't contain what was just written to it.", self.path);
+        }
+
+        self.record_section(content);
+        Ok(())
+    }
+
+    fn write_privileged(&self, content: &str) -> Result<()> {
         // Write to a temporary file first
         let temp_path = "/tmp/make-your-choice-hosts.tmp";
         fs::write(temp_path, content)
             .context("Failed to write temporary file")?;
 
         // Combine all operations into a single pkexec call to avoid multiple prompts
-        let combined_command = format!(
-            "cp {} {}.bak 2>/dev/null || true && cp {} {} && (systemd-resolve --flush-caches 2>/dev/null || resolvectl flush-caches 2>/dev/null || nscd -i hosts 2>/dev/null || true)",
-            HOSTS_PATH, HOSTS_PATH, temp_path, HOSTS_PATH
+        let caches = if self.is_system_file() { dns_cache::detect() } else { Vec::new() };
+        let path = shell_quote(&self.path);
+        let mut combined_command = format!(
+            "(cp {} {}.bak 2>/dev/null || true) && cp {} {}",
+            path, path, temp_path, path
         );
+        if !caches.is_empty() {
+            combined_command.push_str(&format!(" && {{ {}; }}", dns_cache::flush_script(&caches)));
+        }
 
-        let status = Command::new("pkexec")
+        let output = Command::new("pkexec")
             .arg("sh")
             .arg("-c")
             .arg(&combined_command)
-            .status()
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
             .context("Failed to execute pkexec")?;
 
         // Clean up temp file
         let _ = fs::remove_file(temp_path);
 
-        if !status.success() {
-            bail!("Failed to write to {}. Operation was cancelled or permission was denied.", HOSTS_PATH);
+        if !output.status.success() {
+            bail!("Failed to write to {}. Operation was cancelled or permission was denied.", self.path);
         }
 
+        let report = FlushReport::parse(&caches, &String::from_utf8_lossy(&output.stdout));
+        *self.last_flush.lock().unwrap() = Some(report);
         Ok(())
     }
 
-    fn write_wrapped_section(&self, inner_content: &str) -> Result<()> {
-        let original = self.read_hosts()?;
-
-        // Find existing markers
-        let first = original.find(SECTION_MARKER);
-        let last = if let Some(pos) = first {
-            original[pos + SECTION_MARKER.len()..].find(SECTION_MARKER)
-                .map(|p| p + pos + SECTION_MARKER.len())
+    // True when the current user can write the file, or create it, without pkexec
+    pub fn writable_by_user(&self) -> bool {
+        let path = Path::new(&self.path);
+        let target = if path.exists() {
+            path
         } else {
-            None
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            }
         };
 
-        // Build new wrapped block
-        let wrapped = if inner_content.is_empty() {
-            String::new()
+        match CString::new(target.as_os_str().as_bytes()) {
+            Ok(c_path) => unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 },
+            Err(_) => false,
+        }
+    }
+
+    // Config files that belong to the managed hosts file, kept apart for every alternate path
+    fn state_file(&self, name: &str) -> PathBuf {
+        let dir = &self.state_dir;
+        if self.is_system_file() {
+            dir.join(name)
         } else {
-            let mut content = inner_content.to_string();
-            if !content.ends_with('\n') {
-                content.push('\n');
-            }
-            format!("{}\n{}{}\n", SECTION_MARKER, content, SECTION_MARKER)
-        };
+            dir.join(format!("{}.{:016x}", name, checksum(&self.path)))
+        }
+    }
 
-        let new_content = match (first, last) {
-            (Some(f), Some(l)) => {
-                // Replace everything between markers
-                format!("{}{}{}", &original[..f], wrapped, &original[l + SECTION_MARKER.len()..])
+    fn checksum_file(&self) -> PathBuf {
+        self.state_file("hosts-section.sum")
+    }
+
+    // Remembers the checksum of the managed section in `content`, so later edits by other
+    // programs can be told apart from ours
+    fn record_section(&self, content: &str) {
+        let (blocks, _) = managed_blocks(content);
+        match blocks.as_slice() {
+            [block] => {
+                let _ = fs::create_dir_all(&self.state_dir);
+                let _ = fs::write(self.checksum_file(), format!("{:016x}\n", checksum(&content[block.clone()])));
             }
-            (Some(f), None) => {
-                // Corrupt state: replace from first marker to end
-                format!("{}{}", &original[..f], wrapped)
+            _ => {
+                let _ = fs::remove_file(self.checksum_file());
             }
-            (None, _) => {
-                // No markers: append
-                let suffix = if original.ends_with('\n') { "\n" } else { "\n\n" };
-                format!("{}{}{}", original, suffix, wrapped)
-            }
-        };
+        }
+    }
+
+    fn recorded_checksum(&self) -> Option<u64> {
+        let content = fs::read_to_string(self.checksum_file()).ok()?;
+        u64::from_str_radix(content.trim(), 16).ok()
+    }
+
+    // Checks the hosts file against what was last written, meant to run on startup
+    pub fn section_issue(&self) -> Result<Option<SectionIssue>> {
+        let content = self.read_hosts()?;
+        Ok(find_section_issue(&content, self.recorded_checksum()))
+    }
+
+    // Leaves only the section that was last written, dropping copies, stray markers and sections
+    // edited by someone else. A missing section is only forgotten, applying again brings it back.
+    pub fn repair_section(&self) -> Result<()> {
+        let content = self.read_hosts()?;
+        let repaired = repair_sections(&content, self.recorded_checksum());
+        if repaired == content {
+            self.record_section(&content);
+            return Ok(());
+        }
+        self.write_hosts(&repaired)
+    }
+
+    // Takes the hosts file as it is now as the reference for future checks
+    pub fn accept_section(&self) -> Result<()> {
+        let content = self.read_hosts()?;
+        self.record_section(&content);
+        Ok(())
+    }
+
+    // What the last write did to the DNS caches, None if nothing was written since the last call
+    pub fn take_flush_report(&self) -> Option<FlushReport> {
+        self.last_flush.lock().unwrap().take()
+    }
+
+    // Something that makes the hosts file unwritable, even for root
+    pub fn obstacle(&self) -> Option<Obstacle> {
+        hosts_access::check(&self.path)
+    }
+
+    // Puts back content saved earlier with current_content, only asking for a password if needed
+    pub fn restore(&self, content: &str) -> Result<bool> {
+        if self.read_hosts()? == content {
+            return Ok(false);
+        }
+        self.write_hosts(content)?;
+        Ok(true)
+    }
+
+    pub fn current_content(&self) -> Result<String> {
+        self.read_hosts()
+    }
 
+    // The whole hosts file as it would look with `inner_content` as the managed section
+    pub fn planned_content(&self, inner_content: &str) -> Result<String> {
+        let original = self.read_hosts()?;
+        Ok(wrap_section(&original, inner_content))
+    }
+
+    fn write_wrapped_section(&self, inner_content: &str) -> Result<()> {
+        let new_content = self.planned_content(inner_content)?;
         self.write_hosts(&new_content)
     }
 
@@ -99,67 +267,22 @@ impl HostsManager {
         selected: &HashSet<String>,
         block_mode: BlockMode,
         merge_unstable: bool,
+        merge_with_stable: bool,
     ) -> Result<()> {
-        if selected.is_empty() {
-            bail!("Please select at least one server to allow.");
-        }
-
-        // Check if any stable servers are selected
-        let any_stable_selected = selected.iter()
-            .any(|r| regions.get(r).map(|info| info.stable).unwrap_or(false));
-
-        // Merge unstable servers with stable alternatives if needed
-        let mut allowed_set = selected.clone();
-        if merge_unstable && !any_stable_selected {
-            for region in selected.iter() {
-                if let Some(info) = regions.get(region) {
-                    if !info.stable {
-                        let group = get_group_name(region);
-                        // Find a stable alternative in the same group
-                        if let Some((alt_region, _)) = regions.iter()
-                            .find(|(r, i)| get_group_name(r) == group && i.stable)
-                        {
-                            allowed_set.insert(alt_region.clone());
-                        }
-                    }
-                }
-            }
-        }
-
-        // Build hosts content
-        let mut content = String::new();
-        content.push_str("# Edited by Make Your Choice (DbD Server Selector)\n");
-        content.push_str("# Unselected servers are blocked (Gatekeep Mode); selected servers are commented out.\n");
-        content.push_str(&format!("# Need help? Discord: {}\n", self.discord_url));
-        content.push_str("\n");
-
-        for (region_key, region_info) in regions.iter() {
-            let allow = allowed_set.contains(region_key);
-            for host in &region_info.hosts {
-                let is_ping = host.to_lowercase().contains("ping");
-                let include = match block_mode {
-                    BlockMode::Both => true,
-                    BlockMode::OnlyPing => is_ping,
-                    BlockMode::OnlyService => !is_ping,
-                };
-
-                if include {
-                    let prefix = if allow { "#" } else { "0.0.0.0" };
-                    content.push_str(&format!("{:9} {}\n", prefix, host));
-                }
-            }
-            content.push_str("\n");
-        }
-
-        for (_region_key, region_info) in blocked_regions.iter() {
-            for host in &region_info.hosts {
-                content.push_str(&format!("{:9} {}\n", "0.0.0.0", host));
-            }
-            content.push_str("\n");
-        }
+        let plan = self.gatekeep_plan(regions, blocked_regions, selected, block_mode, merge_unstable, merge_with_stable)?;
+        self.write_wrapped_section(&plan.content)
+    }
 
-        self.write_wrapped_section(&content)?;
-        Ok(())
+    pub fn gatekeep_plan(
+        &self,
+        regions: &HashMap<String, RegionInfo>,
+        blocked_regions: &HashMap<String, RegionInfo>,
+        selected: &HashSet<String>,
+        block_mode: BlockMode,
+        merge_unstable: bool,
+        merge_with_stable: bool,
+    ) -> Result<SectionPlan> {
+        plan_gatekeep(&self.discord_url, regions, blocked_regions, selected, block_mode, merge_unstable, merge_with_stable)
     }
 
     pub fn apply_universal_redirect(
@@ -167,46 +290,115 @@ impl HostsManager {
         regions: &HashMap<String, RegionInfo>,
         blocked_regions: &HashMap<String, RegionInfo>,
         selected_region: &str,
+    ) -> Result<RedirectIps> {
+        let ips = resolve_redirect_ips(regions, selected_region)?;
+        self.write_universal_redirect(regions, blocked_regions, &ips)?;
+        Ok(ips)
+    }
+
+    pub fn write_universal_redirect(
+        &self,
+        regions: &HashMap<String, RegionInfo>,
+        blocked_regions: &HashMap<String, RegionInfo>,
+        ips: &RedirectIps,
     ) -> Result<()> {
-        let region_info = regions.get(selected_region)
-            .context("Selected region not found")?;
+        let plan = self.redirect_plan(regions, blocked_regions, ips);
+        self.write_wrapped_section(&plan.content)
+    }
 
-        let service_host = &region_info.hosts[0];
-        let ping_host = if region_info.hosts.len() > 1 {
-            &region_info.hosts[1]
-        } else {
-            &region_info.hosts[0]
+    pub fn redirect_plan(
+        &self,
+        regions: &HashMap<String, RegionInfo>,
+        blocked_regions: &HashMap<String, RegionInfo>,
+        ips: &RedirectIps,
+    ) -> SectionPlan {
+        plan_redirect(&self.discord_url, regions, blocked_regions, ips)
+    }
+
+    // Reads back the addresses currently pinned for `region_info` by a Universal Redirect section.
+    // Returns None when the managed section is missing or was written in another mode.
+    pub fn pinned_redirect_ips(&self, region_info: &RegionInfo) -> Result<Option<RedirectIps>> {
+        let original = self.read_hosts()?;
+        let inner = match find_section(&original) {
+            (Some(f), Some(l)) => &original[f + SECTION_MARKER.len()..l],
+            _ => return Ok(None),
         };
 
-        // Resolve IP addresses
-        let service_ip = resolve_hostname(service_host)?;
-        let ping_ip = resolve_hostname(ping_host)?;
+        if !inner.lines().any(|line| line.trim() == REDIRECT_HEADER) {
+            return Ok(None);
+        }
 
-        // Build hosts content
-        let mut content = String::new();
-        content.push_str("# Edited by Make Your Choice (DbD Server Selector)\n");
-        content.push_str("# Universal Redirect mode: redirect all GameLift endpoints to selected region\n");
-        content.push_str(&format!("# Need help? Discord: {}\n", self.discord_url));
-        content.push_str("\n");
+        let (service_host, ping_host) = redirect_hosts(region_info);
+        let lookup = |hostname: &str| {
+            inner.lines().find_map(|line| {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                if parts.len() >= 2 && !parts[0].starts_with('#') && parts[1].eq_ignore_ascii_case(hostname) {
+                    Some(parts[0].to_string())
+                } else {
+                    None
+                }
+            })
+        };
 
-        for (_, region_info) in regions.iter() {
-            for host in &region_info.hosts {
-                let is_ping = host.to_lowercase().contains("ping");
-                let ip = if is_ping { &ping_ip } else { &service_ip };
-                content.push_str(&format!("{} {}\n", ip, host));
-            }
-            content.push_str("\n");
+        match (lookup(service_host), lookup(ping_host)) {
+            (Some(service), Some(ping)) => Ok(Some(RedirectIps { service, ping })),
+            _ => Ok(None),
         }
+    }
 
-        for (_, region_info) in blocked_regions.iter() {
-            for host in &region_info.hosts {
-                content.push_str(&format!("{} {}\n", "0.0.0.0", host));
+    // What the managed section currently does, read back from the file
+    pub fn applied_state(&self, regions: &HashMap<String, RegionInfo>) -> Result<AppliedState> {
+        let original = self.read_hosts()?;
+        let inner = match find_section(&original) {
+            (Some(f), Some(l)) => &original[f + SECTION_MARKER.len()..l],
+            _ => return Ok(AppliedState::NotApplied),
+        };
+
+        if inner.lines().any(|line| line.trim() == REDIRECT_HEADER) {
+            return Ok(AppliedState::UniversalRedirect);
+        }
+
+        // Gatekeep lists allowed servers commented out and blocked ones with 0.0.0.0
+        let mut commented = HashSet::new();
+        let mut blocked = HashSet::new();
+        for line in inner.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["#", host] => {
+                    commented.insert(host.to_lowercase());
+                }
+                ["0.0.0.0", host] => {
+                    blocked.insert(host.to_lowercase());
+                }
+                _ => {}
             }
-            content.push_str("\n");
         }
 
-        self.write_wrapped_section(&content)?;
-        Ok(())
+        let allowed = regions.iter()
+            .filter(|(_, info)| {
+                let listed = |set: &HashSet<String>| info.hosts.iter().any(|host| set.contains(&host.to_lowercase()));
+                listed(&commented) && !listed(&blocked)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        Ok(AppliedState::Gatekeep { allowed })
+    }
+
+    pub fn has_managed_section(&self) -> Result<bool> {
+        let original = self.read_hosts()?;
+        Ok(find_section(&original).0.is_some())
+    }
+
+    // True when the managed section was written in Universal Redirect mode
+    pub fn has_redirect_section(&self) -> Result<bool> {
+        let original = self.read_hosts()?;
+        match find_section(&original) {
+            (Some(f), Some(l)) => Ok(original[f + SECTION_MARKER.len()..l]
+                .lines()
+                .any(|line| line.trim() == REDIRECT_HEADER)),
+            _ => Ok(false),
+        }
     }
 
     pub fn revert(&self) -> Result<()> {
@@ -214,17 +406,49 @@ impl HostsManager {
         Ok(())
     }
 
+    // The distribution's default hosts file for this machine, for previewing a reset
+    pub fn default_content(&self) -> Result<String> {
+        let current = self.read_hosts()?;
+        let family = Distro::detect().family();
+        Ok(default_hosts(family, distro::hostname().as_deref(), &current))
+    }
+
+    // Overwrites the hosts file with `default_content`, keeping a copy of what was there so
+    // the reset can be undone
     pub fn restore_default(&self) -> Result<()> {
-        let default_hosts = "# Static table lookup for hostnames.
-# See hosts(5) for details.
-127.0.0.1        localhost
-::1              localhost
-";
+        let current = self.read_hosts()?;
+        let default_hosts = self.default_content()?;
+
+        self.write_hosts(&default_hosts)?;
 
-        self.write_hosts(default_hosts)?;
+        // Only offered as undo once the reset happened, a failed write leaves nothing to undo
+        fs::create_dir_all(&self.state_dir)
+            .with_context(|| format!("The hosts file was reset, but the config directory {:?} for undoing it couldn't be created", self.state_dir))?;
+        fs::write(self.reset_backup_file(), &current)
+            .context("The hosts file was reset, but the copy for undoing it couldn't be saved")?;
         Ok(())
     }
 
+    fn reset_backup_file(&self) -> PathBuf {
+        self.state_file("hosts.before-reset")
+    }
+
+    pub fn has_reset_backup(&self) -> bool {
+        self.reset_backup_file().exists()
+    }
+
+    // Puts back the hosts file from before the last reset. Returns false if there was nothing to undo.
+    pub fn undo_restore_default(&self) -> Result<bool> {
+        let backup = match fs::read_to_string(self.reset_backup_file()) {
+            Ok(backup) => backup,
+            Err(_) => return Ok(false),
+        };
+
+        self.restore(&backup)?;
+        let _ = fs::remove_file(self.reset_backup_file());
+        Ok(true)
+    }
+
     pub fn get_all_managed_hostnames(&self, regions: &HashMap<String, RegionInfo>) -> HashSet<String> {
         let mut hostnames = HashSet::new();
         for region_info in regions.values() {
@@ -242,13 +466,7 @@ impl HostsManager {
         let original = self.read_hosts()?;
 
         // Find the section markers
-        let first = original.find(SECTION_MARKER);
-        let last = if let Some(pos) = first {
-            original[pos + SECTION_MARKER.len()..].find(SECTION_MARKER)
-                .map(|p| p + pos + SECTION_MARKER.len())
-        } else {
-            None
-        };
+        let (first, last) = find_section(&original);
 
         // Get content outside markers
         let outside_content = match (first, last) {
@@ -293,30 +511,494 @@ impl HostsManager {
 
     pub fn clear_conflicting_entries(&self, conflicts: &[String]) -> Result<()> {
         let original = self.read_hosts()?;
-        let conflict_set: HashSet<String> = conflicts.iter().map(|s| s.trim().to_string()).collect();
-
-        // Filter out conflicting lines
-        let cleaned: String = original
-            .lines()
-            .filter(|line| !conflict_set.contains(&line.trim().to_string()))
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        // Add trailing newline if original had one
-        let cleaned = if original.ends_with('\n') {
-            format!("{}\n", cleaned)
-        } else {
-            cleaned
-        };
+        let conflict_set: HashSet<&str> = conflicts.iter().map(|s| s.trim()).collect();
+        let (blocks, stray) = managed_blocks(&original);
+
+        // Filter out conflicting lines outside the managed section, keeping line endings as they are
+        let mut cleaned = String::with_capacity(original.len());
+        let mut offset = 0;
+        for line in original.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+
+            let managed = blocks.iter().any(|block| block.contains(&start)) || stray.is_some_and(|s| start >= s);
+            if !managed && conflict_set.contains(line.trim()) {
+                continue;
+            }
+            cleaned.push_str(line);
+        }
 
         self.write_hosts(&cleaned)?;
         Ok(())
     }
 }
 
+// What a line in the managed section does to a hostname
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostAction {
+    // Listed commented out, so it resolves normally
+    Allow,
+    Block,
+    // Pinned to this address
+    Redirect(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionPlan {
+    pub name: String,
+    pub hosts: Vec<(String, HostAction)>,
+}
+
+// A managed section worked out without touching the system: the content to write between the
+// markers and what it does, region by region in the order it is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionPlan {
+    pub content: String,
+    pub regions: Vec<RegionPlan>,
+    // Unstable regions of the selection and the stable fallback merging allowed for each
+    pub fallbacks: Vec<(String, String)>,
+}
+
+impl SectionPlan {
+    // Regions with at least one hostname handled that way
+    pub fn regions_with(&self, action: &HostAction) -> Vec<&str> {
+        self.regions.iter()
+            .filter(|region| region.hosts.iter().any(|(_, a)| a == action))
+            .map(|region| region.name.as_str())
+            .collect()
+    }
+}
+
+// Which hostnames Gatekeep blocks and allows. Hostnames the block mode leaves out get no entry.
+// Regions come in name order so the same selection always produces the same section.
+pub fn gatekeep_regions(
+    regions: &HashMap<String, RegionInfo>,
+    blocked_regions: &HashMap<String, RegionInfo>,
+    selected: &HashSet<String>,
+    block_mode: BlockMode,
+    merge_unstable: bool,
+    merge_with_stable: bool,
+) -> Result<Vec<RegionPlan>> {
+    if selected.is_empty() {
+        bail!("Please select at least one server to allow.");
+    }
+
+    let allowed_set = get_allowed_regions(regions, selected, merge_unstable, merge_with_stable);
+    let mut plan = Vec::new();
+
+    for (region_key, region_info) in sorted(regions) {
+        let allow = allowed_set.contains(region_key);
+        let hosts = region_info.hosts.iter()
+            .filter(|host| {
+                let is_ping = host.to_lowercase().contains("ping");
+                match block_mode {
+                    BlockMode::Both => true,
+                    BlockMode::OnlyPing => is_ping,
+                    BlockMode::OnlyService => !is_ping,
+                }
+            })
+            .map(|host| (host.clone(), if allow { HostAction::Allow } else { HostAction::Block }))
+            .collect();
+        plan.push(RegionPlan { name: region_key.clone(), hosts });
+    }
+
+    plan.extend(always_blocked(blocked_regions));
+    Ok(plan)
+}
+
+// Which address each hostname is pinned to in Universal Redirect mode
+pub fn redirect_regions(
+    regions: &HashMap<String, RegionInfo>,
+    blocked_regions: &HashMap<String, RegionInfo>,
+    ips: &RedirectIps,
+) -> Vec<RegionPlan> {
+    let mut plan: Vec<RegionPlan> = sorted(regions).into_iter()
+        .map(|(region_key, region_info)| {
+            let hosts = region_info.hosts.iter()
+                .map(|host| {
+                    let is_ping = host.to_lowercase().contains("ping");
+                    let ip = if is_ping { &ips.ping } else { &ips.service };
+                    (host.clone(), HostAction::Redirect(ip.clone()))
+                })
+                .collect();
+            RegionPlan { name: region_key.clone(), hosts }
+        })
+        .collect();
+
+    plan.extend(always_blocked(blocked_regions));
+    plan
+}
+
+fn always_blocked(blocked_regions: &HashMap<String, RegionInfo>) -> Vec<RegionPlan> {
+    sorted(blocked_regions).into_iter()
+        .map(|(region_key, region_info)| RegionPlan {
+            name: region_key.clone(),
+            hosts: region_info.hosts.iter().map(|host| (host.clone(), HostAction::Block)).collect(),
+        })
+        .collect()
+}
+
+pub fn plan_gatekeep(
+    discord_url: &str,
+    regions: &HashMap<String, RegionInfo>,
+    blocked_regions: &HashMap<String, RegionInfo>,
+    selected: &HashSet<String>,
+    block_mode: BlockMode,
+    merge_unstable: bool,
+    merge_with_stable: bool,
+) -> Result<SectionPlan> {
+    let fallbacks = get_fallbacks(regions, selected, merge_unstable, merge_with_stable);
+    let regions = gatekeep_regions(regions, blocked_regions, selected, block_mode, merge_unstable, merge_with_stable)?;
+
+    // Build hosts content
+    let mut content = String::new();
+    content.push_str("# Edited by Make Your Choice (DbD Server Selector)\n");
+    content.push_str("# Unselected servers are blocked (Gatekeep Mode); selected servers are commented out.\n");
+    content.push_str(&format!("# Need help? Discord: {}\n", discord_url));
+    content.push('\n');
+    // Gatekeep pads the first column so allowed and blocked hostnames line up
+    render_regions(&mut content, &regions, 9);
+
+    Ok(SectionPlan { content, regions, fallbacks })
+}
+
+// The section for already resolved addresses; resolve_redirect_ips does the lookups
+pub fn plan_redirect(
+    discord_url: &str,
+    regions: &HashMap<String, RegionInfo>,
+    blocked_regions: &HashMap<String, RegionInfo>,
+    ips: &RedirectIps,
+) -> SectionPlan {
+    let regions = redirect_regions(regions, blocked_regions, ips);
+
+    // Build hosts content
+    let mut content = String::new();
+    content.push_str("# Edited by Make Your Choice (DbD Server Selector)\n");
+    content.push_str(REDIRECT_HEADER);
+    content.push('\n');
+    content.push_str(&format!("# Need help? Discord: {}\n", discord_url));
+    content.push('\n');
+    render_regions(&mut content, &regions, 0);
+
+    SectionPlan { content, regions, fallbacks: Vec::new() }
+}
+
+// One line per hostname and a blank line after each region
+fn render_regions(content: &mut String, regions: &[RegionPlan], width: usize) {
+    for region in regions {
+        for (host, action) in &region.hosts {
+            let first = match action {
+                HostAction::Allow => "#",
+                HostAction::Block => "0.0.0.0",
+                HostAction::Redirect(ip) => ip,
+            };
+            content.push_str(&format!("{:width$} {}\n", first, host, width = width));
+        }
+        content.push('\n');
+    }
+}
+
+fn sorted(regions: &HashMap<String, RegionInfo>) -> Vec<(&String, &RegionInfo)> {
+    let mut regions: Vec<_> = regions.iter().collect();
+    regions.sort_by(|a, b| a.0.cmp(b.0));
+    regions
+}
+
+pub fn resolve_redirect_ips(regions: &HashMap<String, RegionInfo>, selected_region: &str) -> Result<RedirectIps> {
+    let region_info = regions.get(selected_region)
+        .context("Selected region not found")?;
+
+    let (service_host, ping_host) = redirect_hosts(region_info);
+
+    // Resolve IP addresses
+    Ok(RedirectIps {
+        service: resolve_hostname(service_host)?,
+        ping: resolve_hostname(ping_host)?,
+    })
+}
+
+// Service endpoint first, ping beacon second; regions with a single host use it for both
+pub fn redirect_hosts(region_info: &RegionInfo) -> (&str, &str) {
+    let service_host = &region_info.hosts[0];
+    let ping_host = if region_info.hosts.len() > 1 {
+        &region_info.hosts[1]
+    } else {
+        &region_info.hosts[0]
+    };
+    (service_host, ping_host)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppliedState {
+    NotApplied,
+    Gatekeep { allowed: HashSet<String> },
+    // The target region isn't recorded in the file, only in the settings
+    UniversalRedirect,
+}
+
+// Ways the managed section can differ from what Make Your Choice last wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionIssue {
+    // More than one section, e.g. another tool merged an old copy of the file back in
+    Duplicated { sections: usize },
+    // A marker without its partner
+    Unterminated,
+    // The section was edited by something else
+    Drifted,
+    // The section was written but has been removed
+    Missing,
+}
+
+impl SectionIssue {
+    pub fn describe(&self, path: &str) -> String {
+        match self {
+            SectionIssue::Duplicated { sections } => format!(
+                "{} contains {} Make Your Choice sections instead of one. Another program probably copied an old version of the file back.\n\n\
+                Repairing keeps the section Make Your Choice wrote last and removes the copies.",
+                path, sections
+            ),
+            SectionIssue::Unterminated => format!(
+                "{} contains a Make Your Choice marker without its partner, so the section can't be told apart from your own entries.\n\n\
+                Repairing removes the stray marker. Leftover server entries are offered for removal the next time you apply.",
+                path
+            ),
+            SectionIssue::Drifted => format!(
+                "The Make Your Choice section in {} was changed by another program since it was last applied.\n\n\
+                Repairing removes the changed section, apply your selection again afterwards.",
+                path
+            ),
+            SectionIssue::Missing => format!(
+                "The Make Your Choice section was removed from {} by another program, so your selection is no longer in effect.\n\n\
+                Apply your selection again to restore it.",
+                path
+            ),
+        }
+    }
+}
+
+// Start offsets of the lines that consist of just the section marker
+fn marker_lines(content: &str) -> Vec<usize> {
+    let mut markers = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim_end() == SECTION_MARKER {
+            markers.push(offset);
+        }
+        offset += line.len();
+    }
+    markers
+}
+
+// Byte ranges of complete managed sections (both marker lines included) and the start of a
+// last marker left without a partner
+fn managed_blocks(content: &str) -> (Vec<std::ops::Range<usize>>, Option<usize>) {
+    let markers = marker_lines(content);
+    let blocks = markers.chunks_exact(2)
+        .map(|pair| {
+            let end = content[pair[1]..].find('\n').map(|i| pair[1] + i + 1).unwrap_or(content.len());
+            pair[0]..end
+        })
+        .collect();
+    let stray = if markers.len() % 2 == 1 { markers.last().copied() } else { None };
+    (blocks, stray)
+}
+
+// Single quotes for sh, so paths with spaces survive the pkexec command line
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// FNV-1a, stable across builds unlike the std hasher
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3))
+}
+
+fn find_section_issue(content: &str, recorded: Option<u64>) -> Option<SectionIssue> {
+    let (blocks, stray) = managed_blocks(content);
+    if blocks.len() > 1 {
+        return Some(SectionIssue::Duplicated { sections: blocks.len() });
+    }
+    if stray.is_some() {
+        return Some(SectionIssue::Unterminated);
+    }
+
+    match (blocks.first(), recorded) {
+        (Some(block), Some(sum)) if checksum(&content[block.clone()]) != sum => Some(SectionIssue::Drifted),
+        (None, Some(_)) => Some(SectionIssue::Missing),
+        _ => None,
+    }
+}
+
+// Removes every managed section except the one matching `recorded`, and any stray marker line
+fn repair_sections(content: &str, recorded: Option<u64>) -> String {
+    let (blocks, _) = managed_blocks(content);
+    let keep = blocks.iter()
+        .find(|block| Some(checksum(&content[(*block).clone()])) == recorded)
+        .cloned();
+
+    let mut repaired = String::with_capacity(content.len());
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        if keep.as_ref().is_some_and(|block| block.contains(&start)) {
+            repaired.push_str(line);
+            continue;
+        }
+        if blocks.iter().any(|block| block.contains(&start)) || line.trim_end() == SECTION_MARKER {
+            continue;
+        }
+        repaired.push_str(line);
+    }
+    repaired
+}
+
+// The hosts file a fresh install of `family` ships with, plus the lines of `current` that
+// map the machine's hostname.
+pub fn default_hosts(family: Family, hostname: Option<&str>, current: &str) -> String {
+    let mut content = match family {
+        Family::Debian => "127.0.0.1\tlocalhost\n".to_string(),
+        Family::Fedora => "# Loopback entries; do not change.\n\
+            # For historical reasons, localhost precedes localhost.localdomain:\n\
+            127.0.0.1   localhost localhost.localdomain localhost4 localhost4.localdomain4\n\
+            ::1         localhost localhost.localdomain localhost6 localhost6.localdomain6\n"
+            .to_string(),
+        Family::Suse => "# hosts         This file describes a number of hostname-to-address\n\
+            #               mappings for the TCP/IP subsystem.\n\
+            # See hosts(5) for details.\n\
+            127.0.0.1\tlocalhost\n\
+            ::1\t\tlocalhost ipv6-localhost ipv6-loopback\n\
+            fe00::0\t\tipv6-localnet\n\
+            ff00::0\t\tipv6-mcastprefix\n\
+            ff02::1\t\tipv6-allnodes\n\
+            ff02::2\t\tipv6-allrouters\n\
+            ff02::3\t\tipv6-allhosts\n"
+            .to_string(),
+        Family::Arch | Family::Other => "# Static table lookup for hostnames.\n\
+            # See hosts(5) for details.\n\
+            127.0.0.1        localhost\n\
+            ::1              localhost\n"
+            .to_string(),
+    };
+
+    // Keep mappings of the hostname someone set up by hand, e.g. with a domain name
+    let hostname_lines = hostname.map(|name| hostname_lines(current, name)).unwrap_or_default();
+    content.push_str(&hostname_lines);
+
+    if family == Family::Debian {
+        // Debian and Ubuntu map the hostname to 127.0.1.1 so it resolves without a network,
+        // other distributions leave that to nss-myhostname
+        if let (Some(hostname), true) = (hostname, hostname_lines.is_empty()) {
+            content.push_str(&format!("127.0.1.1\t{}\n", hostname));
+        }
+        content.push_str(DEBIAN_IPV6_LINES);
+    }
+
+    content
+}
+
+// Lines outside the managed section that map `hostname`, with or without a domain
+fn hostname_lines(current: &str, hostname: &str) -> String {
+    let (first, last) = find_section(current);
+    let outside = match (first, last) {
+        (Some(f), Some(l)) => format!("{}{}", &current[..f], &current[l + SECTION_MARKER.len()..]),
+        (Some(f), None) => current[..f].to_string(),
+        (None, _) => current.to_string(),
+    };
+
+    let mut lines = String::new();
+    for line in outside.lines() {
+        let entry = line.split('#').next().unwrap_or("");
+        let mut fields = entry.split_whitespace();
+        if fields.next().is_none() {
+            continue;
+        }
+        if fields.any(|name| name.split('.').next().is_some_and(|name| name.eq_ignore_ascii_case(hostname))) {
+            lines.push_str(line.trim_end());
+            lines.push('\n');
+        }
+    }
+    lines
+}
+
+const DEBIAN_IPV6_LINES: &str = "\n# The following lines are desirable for IPv6 capable hosts\n\
+    ::1     localhost ip6-localhost ip6-loopback\n\
+    ff02::1 ip6-allnodes\n\
+    ff02::2 ip6-allrouters\n";
+
+// Replaces the managed section of `original` with `inner_content`, appending it if there is none.
+// An empty `inner_content` removes the section. Extra copies of the section are dropped, and a
+// marker without its partner is taken as a section cut off at the end of the file.
+fn wrap_section(original: &str, inner_content: &str) -> String {
+    // Keep Windows line endings, e.g. in a Wine prefix
+    let newline = if original.contains("\r\n") { "\r\n" } else { "\n" };
+
+    // Build new wrapped block
+    let wrapped = if inner_content.is_empty() {
+        String::new()
+    } else {
+        let mut content = inner_content.replace("\r\n", "\n");
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        format!("{}\n{}{}\n", SECTION_MARKER, content, SECTION_MARKER).replace('\n', newline)
+    };
+
+    let (blocks, stray) = managed_blocks(original);
+    let Some(first) = blocks.first().map(|block| block.start).or(stray) else {
+        // No markers: append
+        if wrapped.is_empty() {
+            return original.to_string();
+        }
+        // Separated from the existing lines by a blank line
+        let suffix = if original.is_empty() {
+            String::new()
+        } else if original.ends_with('\n') {
+            newline.to_string()
+        } else {
+            newline.repeat(2)
+        };
+        return format!("{}{}{}", original, suffix, wrapped);
+    };
+
+    let end = stray.unwrap_or(original.len());
+    let mut result = String::with_capacity(original.len() + wrapped.len());
+    result.push_str(&original[..first]);
+    result.push_str(&wrapped);
+    let mut pos = first;
+    for block in &blocks {
+        result.push_str(&original[pos..block.start]);
+        pos = block.end;
+    }
+    result.push_str(&original[pos..end]);
+
+    // Removing a section from the end also removes the blank line that was added with it
+    let double_newline = newline.repeat(2);
+    if wrapped.is_empty() && pos == end && result.ends_with(&double_newline) {
+        result.truncate(result.len() - newline.len());
+    }
+
+    result
+}
+
+// Offsets of the first marker and of its partner
+fn find_section(content: &str) -> (Option<usize>, Option<usize>) {
+    let markers = marker_lines(content);
+    (markers.first().copied(), markers.get(1).copied())
+}
+
 fn resolve_hostname(hostname: &str) -> Result<String> {
     use std::net::ToSocketAddrs;
 
+    // Ask the upstream nameservers first, the system resolver would return whatever an
+    // earlier Universal Redirect pinned in /etc/hosts
+    if let Ok(addrs) = dns::resolve_upstream(hostname) {
+        if let Some(addr) = addrs.first() {
+            return Ok(addr.to_string());
+        }
+    }
+
     let addr = format!("{}:443", hostname)
         .to_socket_addrs()
         .with_context(|| format!("Failed to resolve hostname: {}", hostname))?
@@ -325,3 +1007,435 @@ fn resolve_hostname(hostname: &str) -> Result<String> {
 
     Ok(addr.ip().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const LONDON: &str = "Europe (London)";
+    const OHIO: &str = "US East (Ohio)";
+
+    // A hosts file in its own temp directory, removed again on drop
+    struct TempHosts {
+        dir: PathBuf,
+        manager: HostsManager,
+    }
+
+    impl TempHosts {
+        fn new(content: &str) -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+            let dir = std::env::temp_dir().join(format!(
+                "make-your-choice-test-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("hosts");
+            fs::write(&path, content).unwrap();
+
+            // Checksums and backups stay next to the file instead of in the real config directory
+            let manager = HostsManager::with_path("https://discord.example".to_string(), path.to_string_lossy())
+                .with_state_dir(dir.join("config"));
+            Self { dir, manager }
+        }
+
+        fn content(&self) -> String {
+            fs::read_to_string(self.manager.path()).unwrap()
+        }
+
+        fn apply(&self, inner: &str) -> String {
+            self.manager.write_wrapped_section(inner).unwrap();
+            self.content()
+        }
+    }
+
+    impl Drop for TempHosts {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn regions() -> HashMap<String, RegionInfo> {
+        let region = |code: &str| RegionInfo {
+            hosts: vec![
+                format!("gamelift.{}.amazonaws.com", code),
+                format!("gamelift-ping.{}.api.aws", code),
+            ],
+            stable: true,
+        };
+        HashMap::from([(LONDON.to_string(), region("eu-west-2")), (OHIO.to_string(), region("us-east-2"))])
+    }
+
+    fn gatekeep(manager: &HostsManager, allow: &str) -> String {
+        let selected = HashSet::from([allow.to_string()]);
+        manager.gatekeep_plan(&regions(), &HashMap::new(), &selected, BlockMode::Both, false, false).unwrap().content
+    }
+
+    fn marker_count(content: &str) -> usize {
+        content.lines().filter(|line| line.trim_end() == SECTION_MARKER).count()
+    }
+
+    // Everything that isn't managed: sections removed, a cut-off section dropped
+    fn outside(content: &str) -> String {
+        let (blocks, stray) = managed_blocks(content);
+        let end = stray.unwrap_or(content.len());
+        let mut text = String::new();
+        let mut pos = 0;
+        for block in &blocks {
+            text.push_str(&content[pos..block.start]);
+            pos = block.end;
+        }
+        text.push_str(&content[pos..end]);
+        text
+    }
+
+    fn assert_one_section(content: &str, inner: &str) {
+        let (blocks, stray) = managed_blocks(content);
+        assert_eq!(blocks.len(), 1, "expected one section in:\n{}", content);
+        assert_eq!(stray, None);
+        let section = content[blocks[0].clone()].replace("\r\n", "\n");
+        assert!(section.contains(inner.trim_end()), "section lost its content:\n{}", section);
+    }
+
+    // The untouched lines may only have gained the blank line that separates the section
+    fn assert_outside_kept(before: &str, after: &str) {
+        let (before, after) = (outside(before), outside(after));
+        assert!(after.starts_with(&before), "lines outside the section changed:\n{:?}\n{:?}", before, after);
+        assert!(after[before.len()..].chars().all(|c| c == '\r' || c == '\n'));
+    }
+
+    #[test]
+    fn no_markers_appends_section_after_blank_line() {
+        let hosts = TempHosts::new("127.0.0.1 localhost\n");
+        let content = hosts.apply("0.0.0.0 a.example\n");
+        assert_eq!(content, format!("127.0.0.1 localhost\n\n{m}\n0.0.0.0 a.example\n{m}\n", m = SECTION_MARKER));
+    }
+
+    #[test]
+    fn missing_trailing_newline_is_completed() {
+        let hosts = TempHosts::new("127.0.0.1 localhost");
+        let content = hosts.apply("0.0.0.0 a.example");
+        assert_eq!(content, format!("127.0.0.1 localhost\n\n{m}\n0.0.0.0 a.example\n{m}\n", m = SECTION_MARKER));
+    }
+
+    #[test]
+    fn empty_file_gets_only_the_section() {
+        let hosts = TempHosts::new("");
+        let content = hosts.apply("0.0.0.0 a.example\n");
+        assert_eq!(content, format!("{m}\n0.0.0.0 a.example\n{m}\n", m = SECTION_MARKER));
+    }
+
+    #[test]
+    fn existing_section_is_replaced_in_place() {
+        let original = format!("127.0.0.1 localhost\n{m}\nold\n{m}\n192.168.1.2 nas\n", m = SECTION_MARKER);
+        let hosts = TempHosts::new(&original);
+        let content = hosts.apply("new\n");
+        assert_eq!(content, format!("127.0.0.1 localhost\n{m}\nnew\n{m}\n192.168.1.2 nas\n", m = SECTION_MARKER));
+    }
+
+    #[test]
+    fn one_marker_replaces_the_cut_off_section() {
+        let original = format!("127.0.0.1 localhost\n\n{}\n0.0.0.0 old.example\n", SECTION_MARKER);
+        let hosts = TempHosts::new(&original);
+        let content = hosts.apply("new\n");
+        assert_eq!(content, format!("127.0.0.1 localhost\n\n{m}\nnew\n{m}\n", m = SECTION_MARKER));
+    }
+
+    #[test]
+    fn duplicate_sections_collapse_into_one() {
+        let original = format!(
+            "127.0.0.1 localhost\n{m}\nfirst\n{m}\n192.168.1.2 nas\n{m}\nsecond\n{m}\n10.0.0.1 router\n",
+            m = SECTION_MARKER
+        );
+        let hosts = TempHosts::new(&original);
+        let content = hosts.apply("new\n");
+        assert_eq!(
+            content,
+            format!("127.0.0.1 localhost\n{m}\nnew\n{m}\n192.168.1.2 nas\n10.0.0.1 router\n", m = SECTION_MARKER)
+        );
+    }
+
+    #[test]
+    fn marker_inside_a_line_is_not_a_marker() {
+        let original = format!("127.0.0.1 localhost # {}\n", SECTION_MARKER);
+        let hosts = TempHosts::new(&original);
+        let content = hosts.apply("new\n");
+        assert!(content.starts_with(&original));
+        assert_eq!(marker_count(&content), 2);
+    }
+
+    #[test]
+    fn crlf_line_endings_are_kept() {
+        let original = "127.0.0.1 localhost\r\n192.168.1.2 nas\r\n";
+        let hosts = TempHosts::new(original);
+        let content = hosts.apply("0.0.0.0 a.example\n0.0.0.0 b.example\n");
+        assert!(content.starts_with(original));
+        assert!(!content.replace("\r\n", "").contains('\n'), "bare LF in {:?}", content);
+        assert_one_section(&content, "0.0.0.0 a.example");
+
+        // The CRLF section is found again on the next apply
+        let again = hosts.apply("0.0.0.0 a.example\n0.0.0.0 b.example\n");
+        assert_eq!(again, content);
+    }
+
+    #[test]
+    fn repeated_applies_are_idempotent() {
+        let hosts = TempHosts::new("127.0.0.1 localhost\n");
+        let inner = gatekeep(&hosts.manager, LONDON);
+        let first = hosts.apply(&inner);
+        for _ in 0..5 {
+            assert_eq!(hosts.apply(&inner), first);
+        }
+    }
+
+    #[test]
+    fn revert_restores_the_original_file() {
+        let original = "127.0.0.1 localhost\n::1 localhost\n";
+        let hosts = TempHosts::new(original);
+        for allow in [LONDON, OHIO, LONDON] {
+            hosts.apply(&gatekeep(&hosts.manager, allow));
+            assert_eq!(hosts.apply(""), original);
+        }
+    }
+
+    #[test]
+    fn revert_without_section_changes_nothing() {
+        let hosts = TempHosts::new("127.0.0.1 localhost");
+        assert_eq!(hosts.apply(""), "127.0.0.1 localhost");
+    }
+
+    #[test]
+    fn failed_reset_leaves_nothing_to_undo() {
+        // A directory can't be written as a file, not even by root
+        let hosts = TempHosts::new("");
+        fs::remove_file(hosts.manager.path()).unwrap();
+        fs::create_dir(hosts.manager.path()).unwrap();
+
+        assert!(hosts.manager.restore_default().is_err());
+        assert!(!hosts.manager.has_reset_backup());
+        assert!(!hosts.manager.undo_restore_default().unwrap());
+    }
+
+    #[test]
+    fn huge_file_is_kept_intact() {
+        let mut original: String = (0..200_000)
+            .map(|i| format!("10.{}.{}.{} host{}.lan\n", i / 65536, (i / 256) % 256, i % 256, i))
+            .collect();
+        original.push_str("0.0.0.0 gamelift.eu-west-2.amazonaws.com\n");
+        let hosts = TempHosts::new(&original);
+        let inner = gatekeep(&hosts.manager, OHIO);
+
+        let content = hosts.apply(&inner);
+        assert_one_section(&content, &inner);
+        assert_outside_kept(&original, &content);
+        assert_eq!(hosts.apply(&inner), content);
+
+        let conflicts = hosts.manager.detect_conflicting_entries(&regions()).unwrap();
+        assert_eq!(conflicts, vec!["0.0.0.0 gamelift.eu-west-2.amazonaws.com".to_string()]);
+        hosts.manager.clear_conflicting_entries(&conflicts).unwrap();
+        let cleaned = hosts.content();
+        assert_eq!(cleaned.len(), content.len() - "0.0.0.0 gamelift.eu-west-2.amazonaws.com\n".len());
+        assert_one_section(&cleaned, &inner);
+    }
+
+    #[test]
+    fn detect_ignores_the_managed_section_and_comments() {
+        let original = "127.0.0.1 localhost\n\
+            # 0.0.0.0 gamelift.eu-west-2.amazonaws.com\n\
+            0.0.0.0 GameLift.EU-West-2.amazonaws.com\n\
+            0.0.0.0 GameLift.EU-West-2.amazonaws.com\n\
+            1.2.3.4   gamelift-ping.us-east-2.api.aws  \n\
+            1.2.3.4 unrelated.example\n";
+        let hosts = TempHosts::new(original);
+        hosts.apply(&gatekeep(&hosts.manager, LONDON));
+
+        let conflicts = hosts.manager.detect_conflicting_entries(&regions()).unwrap();
+        assert_eq!(
+            conflicts,
+            vec![
+                "0.0.0.0 GameLift.EU-West-2.amazonaws.com".to_string(),
+                "1.2.3.4   gamelift-ping.us-east-2.api.aws".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_ignores_a_cut_off_section() {
+        let original = format!("127.0.0.1 localhost\n{}\n0.0.0.0 gamelift.eu-west-2.amazonaws.com\n", SECTION_MARKER);
+        let hosts = TempHosts::new(&original);
+        assert!(hosts.manager.detect_conflicting_entries(&regions()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn detect_handles_crlf() {
+        let hosts = TempHosts::new("127.0.0.1 localhost\r\n0.0.0.0 gamelift.us-east-2.amazonaws.com\r\n");
+        let conflicts = hosts.manager.detect_conflicting_entries(&regions()).unwrap();
+        assert_eq!(conflicts, vec!["0.0.0.0 gamelift.us-east-2.amazonaws.com".to_string()]);
+    }
+
+    #[test]
+    fn clear_keeps_the_managed_section_and_line_endings() {
+        let hosts = TempHosts::new("127.0.0.1 localhost\r\n0.0.0.0   gamelift.eu-west-2.amazonaws.com\r\n10.0.0.1 router\r\n");
+        // Same text as a line inside the section, which has to stay
+        let inner = "0.0.0.0   gamelift.eu-west-2.amazonaws.com\n";
+        let applied = hosts.apply(inner);
+
+        let conflicts = hosts.manager.detect_conflicting_entries(&regions()).unwrap();
+        hosts.manager.clear_conflicting_entries(&conflicts).unwrap();
+
+        let cleaned = hosts.content();
+        assert_eq!(
+            cleaned,
+            format!(
+                "127.0.0.1 localhost\r\n10.0.0.1 router\r\n\r\n{m}\r\n0.0.0.0   gamelift.eu-west-2.amazonaws.com\r\n{m}\r\n",
+                m = SECTION_MARKER
+            )
+        );
+        assert_eq!(outside(&applied).replace("0.0.0.0   gamelift.eu-west-2.amazonaws.com\r\n", ""), outside(&cleaned));
+        assert!(hosts.manager.detect_conflicting_entries(&regions()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn clear_without_trailing_newline() {
+        let hosts = TempHosts::new("127.0.0.1 localhost\n0.0.0.0 gamelift.us-east-2.amazonaws.com");
+        let conflicts = hosts.manager.detect_conflicting_entries(&regions()).unwrap();
+        hosts.manager.clear_conflicting_entries(&conflicts).unwrap();
+        assert_eq!(hosts.content(), "127.0.0.1 localhost\n");
+    }
+
+    #[test]
+    fn gatekeep_plan_matches_its_content() {
+        let selected = HashSet::from([LONDON.to_string()]);
+        let plan = plan_gatekeep("discord", &regions(), &HashMap::new(), &selected, BlockMode::OnlyPing, false, false).unwrap();
+
+        assert_eq!(plan.regions_with(&HostAction::Allow), vec![LONDON]);
+        assert_eq!(plan.regions_with(&HostAction::Block), vec![OHIO]);
+        assert_eq!(
+            plan.regions[1].hosts,
+            vec![("gamelift-ping.us-east-2.api.aws".to_string(), HostAction::Block)]
+        );
+        assert!(plan.content.contains("#         gamelift-ping.eu-west-2.api.aws\n"));
+        assert!(plan.content.contains("0.0.0.0   gamelift-ping.us-east-2.api.aws\n"));
+        assert!(!plan.content.contains("gamelift.us-east-2.amazonaws.com"));
+    }
+
+    #[test]
+    fn gatekeep_plan_needs_a_selection() {
+        let result = plan_gatekeep("discord", &regions(), &HashMap::new(), &HashSet::new(), BlockMode::Both, false, false);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn redirect_plan_pins_every_hostname() {
+        let ips = RedirectIps { service: "192.0.2.1".to_string(), ping: "192.0.2.2".to_string() };
+        let plan = plan_redirect("discord", &regions(), &HashMap::new(), &ips);
+
+        for region in &plan.regions {
+            for (host, action) in &region.hosts {
+                let ip = if host.contains("ping") { &ips.ping } else { &ips.service };
+                assert_eq!(*action, HostAction::Redirect(ip.clone()));
+                assert!(plan.content.contains(&format!("{} {}\n", ip, host)));
+            }
+        }
+    }
+
+    // xorshift64*, enough to shuffle hosts file fragments reproducibly without a dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545F4914F6CDD1D)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn random_hosts(rng: &mut Rng) -> String {
+        let fragments = [
+            "127.0.0.1 localhost",
+            "::1 localhost ip6-localhost",
+            "# a comment",
+            "",
+            "   ",
+            "192.168.1.2 nas.lan nas",
+            "0.0.0.0 gamelift.eu-west-2.amazonaws.com",
+            "1.2.3.4\tGameLift-Ping.us-east-2.api.aws",
+            "#         gamelift.us-east-2.amazonaws.com",
+            SECTION_MARKER,
+            "# --+ Make Your Choice +-- not a marker",
+        ];
+        let newline = if rng.below(4) == 0 { "\r\n" } else { "\n" };
+        let mut content: String = (0..rng.below(25))
+            .map(|_| format!("{}{}", fragments[rng.below(fragments.len())], newline))
+            .collect();
+        if rng.below(3) == 0 && content.ends_with('\n') {
+            content.truncate(content.len() - newline.len());
+        }
+        content
+    }
+
+    #[test]
+    fn property_apply_leaves_exactly_one_section() {
+        let mut rng = Rng(0x9E3779B97F4A7C15);
+        for _ in 0..300 {
+            let original = random_hosts(&mut rng);
+            let hosts = TempHosts::new(&original);
+            let inner_a = gatekeep(&hosts.manager, LONDON);
+            let inner_b = gatekeep(&hosts.manager, OHIO);
+
+            let applied = hosts.apply(&inner_a);
+            assert_one_section(&applied, &inner_a);
+            assert_outside_kept(&original, &applied);
+            if original.contains("\r\n") {
+                assert!(!applied.replace("\r\n", "").contains('\n'), "mixed line endings: {:?}", applied);
+            }
+
+            // Idempotent, and the last apply wins
+            assert_eq!(hosts.apply(&inner_a), applied);
+            hosts.apply(&inner_b);
+            assert_eq!(hosts.apply(&inner_a), applied);
+
+            // Reverting removes every marker
+            let reverted = hosts.apply("");
+            assert_eq!(marker_count(&reverted), 0, "markers left in {:?}", reverted);
+            if marker_count(&original) == 0 && (original.is_empty() || original.ends_with('\n')) {
+                assert_eq!(reverted, original);
+            }
+        }
+    }
+
+    #[test]
+    fn property_clearing_conflicts_only_removes_conflicts() {
+        let mut rng = Rng(0xD1B54A32D192ED03);
+        for _ in 0..300 {
+            let original = random_hosts(&mut rng);
+            let hosts = TempHosts::new(&original);
+            let applied = hosts.apply(&gatekeep(&hosts.manager, LONDON));
+            let (blocks, _) = managed_blocks(&applied);
+            let section = applied[blocks[0].clone()].to_string();
+
+            let conflicts = hosts.manager.detect_conflicting_entries(&regions()).unwrap();
+            hosts.manager.clear_conflicting_entries(&conflicts).unwrap();
+            let cleaned = hosts.content();
+
+            assert!(hosts.manager.detect_conflicting_entries(&regions()).unwrap().is_empty());
+            let (blocks, _) = managed_blocks(&cleaned);
+            assert_eq!(&cleaned[blocks[0].clone()], section);
+
+            let before = outside(&applied);
+            let kept: Vec<&str> = before
+                .split_inclusive('\n')
+                .filter(|line| !conflicts.iter().any(|c| c == line.trim()))
+                .collect();
+            assert_eq!(outside(&cleaned), kept.concat());
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::distro::Distro;
+use crate::region::ApplyMode;
+use crate::resolved::{self, ResolvedState};
+
+// From linux/fs.h, libc only exports the ioctl number
+const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+// Symlink targets that are regenerated, so anything written there doesn't stick
+const GENERATED_PREFIXES: [&str; 2] = ["/run/", "/nix/store/"];
+
+// Reasons the hosts file can't be changed, even with root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Obstacle {
+    ReadOnlyMount { mount_point: PathBuf },
+    Immutable,
+    GeneratedSymlink { target: PathBuf },
+}
+
+// What `check` found out about the hosts file
+struct Facts {
+    // Where the path leads after following links
+    resolved: PathBuf,
+    is_symlink: bool,
+    read_only: bool,
+    immutable: bool,
+}
+
+// Checked before asking for a password, so the user gets an explanation instead of a failed copy
+pub fn check(path: &str) -> Option<Obstacle> {
+    let path = Path::new(path);
+    let resolved = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let facts = Facts {
+        is_symlink: fs::symlink_metadata(path)
+            .map(|meta| meta.file_type().is_symlink())
+            .unwrap_or(false),
+        read_only: is_read_only_mount(&resolved),
+        immutable: is_immutable(&resolved),
+        resolved,
+    };
+
+    let mountinfo = || fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    classify(&facts, mountinfo)
+}
+
+// A regenerated link target explains the most, it usually lives on a read-only mount as well
+fn classify(facts: &Facts, mountinfo: impl FnOnce() -> String) -> Option<Obstacle> {
+    if facts.is_symlink && GENERATED_PREFIXES.iter().any(|prefix| facts.resolved.starts_with(prefix)) {
+        return Some(Obstacle::GeneratedSymlink { target: facts.resolved.clone() });
+    }
+
+    if facts.read_only {
+        return Some(Obstacle::ReadOnlyMount { mount_point: mount_point(&mountinfo(), &facts.resolved) });
+    }
+
+    if facts.immutable {
+        return Some(Obstacle::Immutable);
+    }
+
+    None
+}
+
+fn is_read_only_mount(path: &Path) -> bool {
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    result == 0 && stat.f_flag & libc::ST_RDONLY != 0
+}
+
+fn is_immutable(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+
+    // The kernel reads and writes an int here despite the ioctl being declared with a long
+    let mut flags: libc::c_int = 0;
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+    result == 0 && flags & FS_IMMUTABLE_FL != 0
+}
+
+// The method to use instead: systemd-resolved when it's in use and its drop-in directory can be
+// written, otherwise the firewall, which doesn't need any file at all
+pub fn alternative() -> ApplyMode {
+    if resolved::detect() == ResolvedState::Usable && !is_read_only_mount(Path::new("/etc/systemd")) {
+        ApplyMode::SystemdResolved
+    } else {
+        ApplyMode::Firewall
+    }
+}
+
+fn method_name(mode: ApplyMode) -> &'static str {
+    match mode {
+        ApplyMode::SystemdResolved => "systemd-resolved",
+        _ => "Firewall",
+    }
+}
+
+// The longest mount point in `mountinfo` (as in /proc/self/mountinfo) that contains `path`
+fn mount_point(mountinfo: &str, path: &Path) -> PathBuf {
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .map(|point| PathBuf::from(point.replace("\\040", " ")))
+        .filter(|point| path.starts_with(point))
+        .max_by_key(|point| point.as_os_str().len())
+        .unwrap_or_else(|| PathBuf::from("/"))
+}
+
+impl Obstacle {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Obstacle::ReadOnlyMount { .. } => "Hosts file is on a read-only system",
+            Obstacle::Immutable => "Hosts file is locked",
+            Obstacle::GeneratedSymlink { .. } => "Hosts file is generated by the system",
+        }
+    }
+
+    // What's going on and what to use instead on this platform
+    pub fn explanation(&self, path: &str, distro: &Distro, alternative: ApplyMode) -> String {
+        let cause = match self {
+            Obstacle::ReadOnlyMount { mount_point } => format!(
+                "{} lives on {}, which is mounted read-only, so it can't be changed even with administrator rights.",
+                path,
+                mount_point.display()
+            ),
+            Obstacle::Immutable => format!(
+                "{} has the immutable attribute set (chattr +i), so nobody can change it, not even root.",
+                path
+            ),
+            Obstacle::GeneratedSymlink { target } => format!(
+                "{} is a link to {}, which the system regenerates, so changes would be lost or rejected.",
+                path,
+                target.display()
+            ),
+        };
+
+        let instead = format!(
+            "Please use the {} method instead, it doesn't touch the hosts file.",
+            method_name(alternative)
+        );
+        let advice = if distro.is_steamos() {
+            format!(
+                "SteamOS keeps its system files read-only and resets them with every update. {} \
+                (\"sudo steamos-readonly disable\" makes the system writable, but only until the next update.)",
+                instead
+            )
+        } else if distro.is_nixos() {
+            format!(
+                "NixOS builds the hosts file from your configuration. {} \
+                Alternatively add the entries with networking.extraHosts in configuration.nix.",
+                instead
+            )
+        } else if *self == Obstacle::Immutable {
+            format!(
+                "If you locked it yourself, unlock it with \"sudo chattr -i {}\". Otherwise: {}",
+                path, instead
+            )
+        } else {
+            instead
+        };
+
+        format!("{}\n\n{}", cause, advice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 0:21 / / ro,relatime shared:1 - btrfs /dev/nvme0n1p2 ro
+23 22 0:22 / /run rw,nosuid,nodev shared:5 - tmpfs tmpfs rw
+24 22 0:23 / /etc rw,relatime shared:6 - overlay overlay rw
+25 22 0:24 / /mnt/game\\040drive ro,relatime shared:7 - ext4 /dev/sdb1 ro
+";
+
+    fn facts(resolved: &str, is_symlink: bool, read_only: bool, immutable: bool) -> Facts {
+        Facts { resolved: PathBuf::from(resolved), is_symlink, read_only, immutable }
+    }
+
+    #[test]
+    fn links_into_generated_places_are_reported_first() {
+        let obstacle = classify(&facts("/run/systemd/resolve/hosts", true, true, true), || MOUNTINFO.to_string());
+        assert_eq!(obstacle, Some(Obstacle::GeneratedSymlink { target: PathBuf::from("/run/systemd/resolve/hosts") }));
+        assert!(matches!(
+            classify(&facts("/nix/store/abc-hosts", true, false, false), String::new),
+            Some(Obstacle::GeneratedSymlink { .. })
+        ));
+
+        // Only a link is regenerated, a file there or a link elsewhere is judged like any file
+        assert_eq!(classify(&facts("/run/hosts", false, false, false), String::new), None);
+        assert_eq!(classify(&facts("/home/me/hosts", true, false, false), String::new), None);
+    }
+
+    #[test]
+    fn read_only_mounts_name_their_mount_point() {
+        assert_eq!(
+            classify(&facts("/etc/hosts", false, true, true), || MOUNTINFO.to_string()),
+            Some(Obstacle::ReadOnlyMount { mount_point: PathBuf::from("/etc") })
+        );
+        // Escaped spaces are decoded, the longest matching mount wins over /
+        assert_eq!(
+            classify(&facts("/mnt/game drive/prefix/hosts", false, true, false), || MOUNTINFO.to_string()),
+            Some(Obstacle::ReadOnlyMount { mount_point: PathBuf::from("/mnt/game drive") })
+        );
+        assert_eq!(mount_point("", Path::new("/etc/hosts")), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn immutable_files_are_reported_last() {
+        assert_eq!(classify(&facts("/etc/hosts", false, false, true), String::new), Some(Obstacle::Immutable));
+        assert_eq!(classify(&facts("/etc/hosts", false, false, false), String::new), None);
+    }
+
+    #[test]
+    fn a_plain_file_has_no_obstacle() {
+        let dir = std::env::temp_dir().join(format!("myc-access-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hosts = dir.join("hosts");
+        fs::write(&hosts, "127.0.0.1 localhost\n").unwrap();
+
+        assert_eq!(check(&hosts.to_string_lossy()), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
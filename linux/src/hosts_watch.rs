@@ -0,0 +1,185 @@
+use anyhow::{Result, bail};
+use std::ffi::CString;
+use std::fs;
+use std::mem;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Editors and most tools replace the file instead of writing into it, so the directory is
+// watched and events are filtered by name
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+
+// Notices changes to a file through inotify. A background thread waits on the inotify
+// descriptor, the UI polls `changed` from a timer.
+pub struct HostsWatcher {
+    rx: Receiver<()>,
+    // Closed on drop, which wakes the thread up so it can close the inotify descriptor and end
+    _stop: OwnedFd,
+}
+
+impl HostsWatcher {
+    pub fn start(path: &str) -> Result<Self> {
+        let path = Path::new(path);
+        // A link is watched where it lives, so it being replaced is noticed, and where it points
+        // to, since writes go to the target
+        let mut files = vec![path.to_path_buf()];
+        if fs::symlink_metadata(path).map(|meta| meta.file_type().is_symlink()).unwrap_or(false) {
+            if let Ok(target) = fs::canonicalize(path) {
+                files.push(target);
+            }
+        }
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            bail!("inotify_init1 failed: {}", std::io::Error::last_os_error());
+        }
+        let inotify = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // Watch descriptor of the directory and the name to look for in it
+        let mut watched: Vec<(i32, String)> = Vec::new();
+        for file in &files {
+            let (Some(dir), Some(name)) = (file.parent(), file.file_name()) else {
+                bail!("Cannot watch {}", file.display());
+            };
+            let dir = CString::new(dir.to_string_lossy().as_bytes())?;
+            let wd = unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), WATCH_MASK) };
+            if wd < 0 {
+                bail!("Cannot watch {}: {}", file.display(), std::io::Error::last_os_error());
+            }
+            watched.push((wd, name.to_string_lossy().into_owned()));
+        }
+
+        let mut pipe = [0; 2];
+        if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            bail!("pipe2 failed: {}", std::io::Error::last_os_error());
+        }
+        let (stop_read, stop_write) = unsafe { (OwnedFd::from_raw_fd(pipe[0]), OwnedFd::from_raw_fd(pipe[1])) };
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut fds = [
+                libc::pollfd { fd, events: libc::POLLIN, revents: 0 },
+                libc::pollfd { fd: pipe[0], events: libc::POLLIN, revents: 0 },
+            ];
+            let mut buffer = [0u8; 4096];
+            loop {
+                if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+                    if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    break;
+                }
+                // The write end was closed, the watcher was dropped
+                if fds[1].revents != 0 {
+                    break;
+                }
+
+                let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+                if read <= 0 {
+                    break;
+                }
+
+                let matches = names_in_events(&buffer[..read as usize])
+                    .any(|event| watched.iter().any(|(wd, name)| event.0 == *wd && event.1 == *name));
+                if matches && tx.send(()).is_err() {
+                    break;
+                }
+            }
+            drop(inotify);
+            drop(stop_read);
+        });
+
+        Ok(Self { rx, _stop: stop_write })
+    }
+
+    // True if the file changed since the last call
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        while self.rx.try_recv().is_ok() {
+            changed = true;
+        }
+        changed
+    }
+}
+
+// Watch descriptors and file names of the inotify events in `buffer`
+fn names_in_events(buffer: &[u8]) -> impl Iterator<Item = (i32, String)> + '_ {
+    let header = mem::size_of::<libc::inotify_event>();
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset + header > buffer.len() {
+            return None;
+        }
+        let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+        let start = offset + header;
+        let end = (start + event.len as usize).min(buffer.len());
+        offset = start + event.len as usize;
+
+        // The name is padded with NULs
+        let name = &buffer[start..end];
+        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+        Some((event.wd, String::from_utf8_lossy(name).into_owned()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // An inotify event as the kernel writes it, the name padded to `padded_len` bytes
+    fn event(wd: i32, name: &str, padded_len: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&wd.to_ne_bytes());
+        bytes.extend_from_slice(&libc::IN_CLOSE_WRITE.to_ne_bytes());
+        bytes.extend_from_slice(&0u32.to_ne_bytes());
+        bytes.extend_from_slice(&(padded_len as u32).to_ne_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.resize(bytes.len() + padded_len - name.len(), 0);
+        bytes
+    }
+
+    fn wait_for_change(watcher: &HostsWatcher) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if watcher.changed() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn reads_padded_names_and_stops_at_a_cut_off_header() {
+        let mut buffer = event(1, "hosts", 16);
+        buffer.extend(event(2, "hosts.new", 32));
+        // A directory event has no name at all
+        buffer.extend(event(1, "", 0));
+        buffer.extend_from_slice(&[1, 0, 0, 0, 2, 0]);
+
+        let names: Vec<(i32, String)> = names_in_events(&buffer).collect();
+        assert_eq!(
+            names,
+            [(1, "hosts".to_string()), (2, "hosts.new".to_string()), (1, String::new())]
+        );
+    }
+
+    #[test]
+    fn notices_writes_through_a_symlink() {
+        let dir = std::env::temp_dir().join(format!("myc-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("target/hosts"), "127.0.0.1 localhost\n").unwrap();
+        let link = dir.join("hosts");
+        let _ = fs::remove_file(&link);
+        std::os::unix::fs::symlink(dir.join("target/hosts"), &link).unwrap();
+
+        let watcher = HostsWatcher::start(&link.to_string_lossy()).unwrap();
+        fs::write(dir.join("target/hosts"), "127.0.0.1 localhost\n0.0.0.0 example.com\n").unwrap();
+        assert!(wait_for_change(&watcher));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use crate::region::{RegionInfo, get_region_code};
+use crate::settings::UserSettings;
+
+pub const IP_RANGES_URL: &str = "https://ip-ranges.amazonaws.com/ip-ranges.json";
+
+// GameLift fleets run on EC2 instances, ip-ranges.json has no service of its own for them.
+// The EC2 ranges of a region also cover everything else hosted on EC2 there.
+const FLEET_SERVICE: &str = "EC2";
+
+// AWS publishes its address ranges at https://ip-ranges.amazonaws.com/ip-ranges.json
+#[derive(Debug, Clone, Deserialize)]
+pub struct IpRanges {
+    #[serde(rename = "syncToken")]
+    pub sync_token: String,
+    #[serde(rename = "createDate")]
+    pub create_date: String,
+    pub prefixes: Vec<Ipv4Prefix>,
+    pub ipv6_prefixes: Vec<Ipv6Prefix>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ipv4Prefix {
+    pub ip_prefix: String,
+    pub region: String,
+    pub service: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ipv6Prefix {
+    pub ipv6_prefix: String,
+    pub region: String,
+    pub service: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegionPrefixes {
+    pub ipv4: Vec<String>,
+    pub ipv6: Vec<String>,
+}
+
+impl RegionPrefixes {
+    pub fn is_empty(&self) -> bool {
+        self.ipv4.is_empty() && self.ipv6.is_empty()
+    }
+
+    pub fn extend(&mut self, other: RegionPrefixes) {
+        self.ipv4.extend(other.ipv4);
+        self.ipv6.extend(other.ipv6);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheUpdate {
+    Updated { sync_token: String },
+    // The cache already holds this or a newer publication
+    AlreadyCurrent { sync_token: String },
+}
+
+impl IpRanges {
+    pub fn cache_file() -> PathBuf {
+        UserSettings::config_dir().join("ip-ranges.json")
+    }
+
+    pub fn load_cached() -> Result<Option<Self>> {
+        let path = Self::cache_file();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::load(&path).map(Some)
+    }
+
+    // Like load_cached, but explains how to get the file when there is none
+    pub fn load_required() -> Result<Self> {
+        Self::load_cached()?.context(
+            "No AWS IP ranges have been downloaded yet.\n\nUse \"Download latest\" in Options → Program settings or run `make-your-choice ip-ranges update`."
+        )
+    }
+
+    // Validates `json` and writes it to the cache unless the cache already has a newer syncToken.
+    pub fn store(json: &str) -> Result<CacheUpdate> {
+        let incoming = Self::parse(json)?;
+
+        if let Ok(Some(cached)) = Self::load_cached() {
+            if cached.sync_token_value() >= incoming.sync_token_value() {
+                return Ok(CacheUpdate::AlreadyCurrent { sync_token: cached.sync_token });
+            }
+        }
+
+        let dir = UserSettings::config_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create config directory {:?}", dir))?;
+        fs::write(Self::cache_file(), json)
+            .context("Failed to write IP ranges cache")?;
+
+        Ok(CacheUpdate::Updated { sync_token: incoming.sync_token })
+    }
+
+    pub fn import(path: &Path) -> Result<CacheUpdate> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        Self::store(&content)
+    }
+
+    pub async fn download() -> Result<String> {
+        let client = reqwest::Client::new();
+        client
+            .get(IP_RANGES_URL)
+            .header("User-Agent", "make-your-choice")
+            .send()
+            .await
+            .context("Failed to download ip-ranges.json")?
+            .error_for_status()
+            .context("Failed to download ip-ranges.json")?
+            .text()
+            .await
+            .context("Failed to download ip-ranges.json")
+    }
+
+    // syncToken is the publication time in Unix seconds
+    pub fn sync_token_value(&self) -> u64 {
+        self.sync_token.parse().unwrap_or(0)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(json: &str) -> Result<Self> {
+        let ranges: IpRanges = serde_json::from_str(json)
+            .context("Failed to parse ip-ranges.json")?;
+
+        // These end up in firewall rules, so refuse anything that isn't a plain CIDR block
+        for prefix in &ranges.prefixes {
+            validate_prefix(&prefix.ip_prefix, false)?;
+        }
+        for prefix in &ranges.ipv6_prefixes {
+            validate_prefix(&prefix.ipv6_prefix, true)?;
+        }
+
+        Ok(ranges)
+    }
+
+    pub fn fleet_prefixes(&self, region_code: &str) -> RegionPrefixes {
+        let ipv4 = self.prefixes.iter()
+            .filter(|p| p.service == FLEET_SERVICE && p.region == region_code)
+            .map(|p| p.ip_prefix.clone())
+            .collect();
+        let ipv6 = self.ipv6_prefixes.iter()
+            .filter(|p| p.service == FLEET_SERVICE && p.region == region_code)
+            .map(|p| p.ipv6_prefix.clone())
+            .collect();
+
+        RegionPrefixes { ipv4, ipv6 }
+    }
+
+    // Fleet prefixes for every region, keyed by the region code from its endpoints
+    pub fn region_prefixes(&self, regions: &HashMap<String, RegionInfo>) -> BTreeMap<String, RegionPrefixes> {
+        regions.values()
+            .filter_map(get_region_code)
+            .map(|code| (code.to_string(), self.fleet_prefixes(code)))
+            .collect()
+    }
+}
+
+fn validate_prefix(prefix: &str, ipv6: bool) -> Result<()> {
+    let (addr, len) = prefix.split_once('/')
+        .with_context(|| format!("Invalid prefix: {}", prefix))?;
+    let addr: IpAddr = addr.parse()
+        .with_context(|| format!("Invalid prefix: {}", prefix))?;
+    let len: u8 = len.parse()
+        .with_context(|| format!("Invalid prefix: {}", prefix))?;
+
+    let max_len = if addr.is_ipv6() { 128 } else { 32 };
+    if addr.is_ipv6() != ipv6 || len > max_len {
+        bail!("Invalid prefix: {}", prefix);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> IpRanges {
+        IpRanges::load(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ip-ranges.json")).unwrap()
+    }
+
+    #[test]
+    fn selects_only_ec2_prefixes_of_the_region() {
+        let ranges = fixture();
+
+        // The AMAZON superset, S3 and Route 53 ranges of the same regions are left out
+        let london = ranges.fleet_prefixes("eu-west-2");
+        assert_eq!(london.ipv4, ["198.51.100.64/26"]);
+        assert_eq!(london.ipv6, ["2001:db8:1::/48"]);
+        let virginia = ranges.fleet_prefixes("us-east-1");
+        assert_eq!(virginia.ipv4, ["198.51.100.128/26"]);
+        assert_eq!(virginia.ipv6, ["2001:db8:2::/48"]);
+
+        assert!(ranges.fleet_prefixes("GLOBAL").is_empty());
+    }
+
+    #[test]
+    fn rejects_prefixes_that_are_not_cidr_blocks() {
+        let json = r#"{"syncToken": "1", "createDate": "x", "prefixes": [
+            {"ip_prefix": "198.51.100.0/26; flush ruleset", "region": "eu-west-2", "service": "EC2", "network_border_group": "eu-west-2"}
+        ], "ipv6_prefixes": []}"#;
+        assert!(IpRanges::parse(json).is_err());
+    }
+}
@@ -1,8 +1,27 @@
+mod backend;
+mod cli;
+mod diff;
+mod distro;
+mod dns;
+mod dns_cache;
+mod dns_proxy;
+mod firewall;
+mod game_install;
+mod game_session;
 mod hosts;
+mod hosts_access;
+mod hosts_watch;
+mod ip_ranges;
 mod ping;
+mod refresh;
 mod region;
+mod resolved;
+mod schedule;
 mod settings;
+mod steam;
+mod systemd;
 mod update;
+mod verify;
 
 use gio::{Menu, SimpleAction};
 use glib::Type;
@@ -11,8 +30,8 @@ use gtk4::{
     gio, glib, pango, Application, ApplicationWindow, Box as GtkBox, Button, ButtonsType,
     CellRendererText, CheckButton, ComboBoxText, Dialog, Entry, FileChooserAction,
     FileChooserNative, FileFilter, Label, ListStore, MenuButton, MessageDialog, MessageType,
-    Orientation, PolicyType, ResponseType, ScrolledWindow, SelectionMode, Separator, TreeView,
-    TreeViewColumn,
+    Orientation, PolicyType, ResponseType, ScrolledWindow, SelectionMode, Separator, TextView,
+    TreeView, TreeViewColumn,
 };
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
@@ -20,12 +39,19 @@ use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
 
+use backend::{ApplyRequest, Backends, Snapshot};
 use hosts::HostsManager;
+use ip_ranges::IpRanges;
 use region::*;
-use settings::UserSettings;
+use settings::{ApplyOptions, Profile, UserSettings};
 use update::UpdateChecker;
+use verify::Expectation;
 
 const APP_ID: &str = "dev.lawliet.makeyourchoice";
+const DISCORD_URL: &str = "https://discord.gg/xEMyAA8gn8";
+// First entry of the profile row, applies with the options from the settings
+const NO_PROFILE: &str = "No profile";
+const INVALID_GAME_FOLDER: &str = "This doesn't look like a Dead by Daylight install. Please select the folder that contains the \"DeadByDaylight\" and \"EasyAntiCheat\" folders.";
 
 #[derive(Debug, serde::Deserialize)]
 struct PatchNotes {
@@ -70,9 +96,13 @@ struct AppState {
         blocked_regions: HashMap<String, RegionInfo>,
     settings: Arc<Mutex<UserSettings>>,
     hosts_manager: HostsManager,
+    backends: Backends,
     update_checker: UpdateChecker,
     selected_regions: RefCell<HashSet<String>>,
+    // Profile picked in the profile row, its options are used instead of the saved ones
+    active_profile: RefCell<Option<String>>,
     list_store: ListStore,
+    status_label: Label,
     tokio_runtime: Arc<Runtime>,
 }
 
@@ -169,16 +199,28 @@ fn main() -> glib::ExitCode {
         std::process::exit(1);
     }
 
+    // Run headless when a command was given (e.g. from the systemd refresh timer)
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (options, args) = match cli::parse_options(&args) {
+        Ok(parsed) => parsed,
+        Err(code) => std::process::exit(code),
+    };
+    if let Some(code) = cli::run(&options, args) {
+        std::process::exit(code);
+    }
+
     let app = Application::builder().application_id(APP_ID).build();
-    app.connect_activate(build_ui);
-    app.run()
+    app.connect_activate(move |app| build_ui(app, &options));
+    // The options were handled above, GTK would reject them as unknown
+    let program: Vec<String> = std::env::args().take(1).collect();
+    app.run_with_args(&program)
 }
 
 fn is_running_as_root() -> bool {
     unsafe { libc::geteuid() == 0 }
 }
 
-fn build_ui(app: &Application) {
+fn build_ui(app: &Application, options: &cli::GlobalOptions) {
     // Create tokio runtime for async operations
     let tokio_runtime = Arc::new(Runtime::new().expect("Failed to create tokio runtime"));
 
@@ -198,12 +240,12 @@ fn build_ui(app: &Application) {
         developer, // Fetched from API
         repo: "make-your-choice".to_string(), // Repository name
         update_message,
-        discord_url: "https://discord.gg/xEMyAA8gn8".to_string(),
+        discord_url: DISCORD_URL.to_string(),
     };
 
     let regions = get_selectable_regions();
         let blocked_regions = get_blocked_regions();
-    let hosts_manager = HostsManager::new(config.discord_url.clone());
+    let hosts_manager = options.hosts_manager(&config.discord_url);
     let update_checker = UpdateChecker::new(
         config.developer.clone().unwrap_or_else(|| "unknown".to_string()),
         config.repo.clone(),
@@ -401,16 +443,28 @@ fn build_ui(app: &Application) {
     scrolled.set_child(Some(&tree_view));
     scrolled.set_vexpand(true);
 
+    // Shows what the hosts file currently does, kept up to date by the hosts watcher
+    let status_label = Label::new(None);
+    status_label.set_wrap(true);
+    status_label.set_max_width_chars(50);
+    status_label.set_margin_start(10);
+    status_label.set_margin_end(10);
+    status_label.set_margin_top(5);
+    status_label.add_css_class("dim-label");
+
     // Create app state
     let app_state = Rc::new(AppState {
         config: config.clone(),
         regions: regions.clone(),
             blocked_regions: blocked_regions.clone(),
         settings: settings.clone(),
+        backends: Backends::new(hosts_manager.clone()),
         hosts_manager,
         update_checker,
         selected_regions: RefCell::new(HashSet::new()),
+        active_profile: RefCell::new(None),
         list_store: list_store.clone(),
+        status_label: status_label.clone(),
         tokio_runtime,
     });
 
@@ -517,6 +571,9 @@ fn build_ui(app: &Application) {
     tip_label.set_margin_top(5);
     tip_label.set_margin_bottom(5);
 
+    // Saved selections, picking one ticks its servers and switches to its method
+    let profile_row = build_profile_row(&app_state, &window);
+
     // Buttons
     let button_box = GtkBox::new(Orientation::Horizontal, 10);
     button_box.set_halign(gtk4::Align::End);
@@ -526,10 +583,12 @@ fn build_ui(app: &Application) {
     button_box.set_margin_bottom(10);
 
     let btn_revert = Button::with_label("Revert to Default");
+    let btn_preview = Button::with_label("Preview changes");
     let btn_apply = Button::with_label("Apply Selection");
     btn_apply.add_css_class("suggested-action");
 
     button_box.append(&btn_revert);
+    button_box.append(&btn_preview);
     button_box.append(&btn_apply);
 
     // Main layout
@@ -537,7 +596,9 @@ fn build_ui(app: &Application) {
     main_box.append(&menu_box);
     main_box.append(&Separator::new(Orientation::Horizontal));
     main_box.append(&tip_label);
+    main_box.append(&profile_row);
     main_box.append(&scrolled);
+    main_box.append(&status_label);
     main_box.append(&button_box);
 
     window.set_child(Some(&main_box));
@@ -549,6 +610,12 @@ fn build_ui(app: &Application) {
         handle_apply_click(&app_state_clone, &window_clone);
     });
 
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    btn_preview.connect_clicked(move |_| {
+        show_preview_dialog(&app_state_clone, &window_clone);
+    });
+
     let app_state_clone = app_state.clone();
     let window_clone = window.clone();
     btn_revert.connect_clicked(move |_| {
@@ -558,10 +625,26 @@ fn build_ui(app: &Application) {
     // Start ping timer
     start_ping_timer(app_state.clone());
 
+    // Keep pinned Universal Redirect IPs current while the app is open
+    start_redirect_refresh_timer(app_state.clone(), &window);
+
+    // Switch profiles at the scheduled times while the app is open
+    start_schedule_timer(app_state.clone(), &window);
+
+    // Apply while the game runs and revert when it exits, if enabled
+    start_game_session_watcher(app_state.clone(), &window);
+
+    // Follow changes other programs make to the hosts file
+    refresh_hosts_status(&app_state);
+    start_hosts_watcher(app_state.clone());
+
     // Check for updates silently on launch
     check_for_updates_silent(&app_state, &window);
 
     window.present();
+
+    // Notice when another program rewrote the managed section since the last run
+    check_hosts_section(&app_state, &window);
 }
 
 fn create_version_menu(_window: &ApplicationWindow, _app_state: &Rc<AppState>) -> Menu {
@@ -570,13 +653,16 @@ fn create_version_menu(_window: &ApplicationWindow, _app_state: &Rc<AppState>) -
     menu.append(Some("Repository (⭐)"), Some("app.repository"));
     menu.append(Some("About"), Some("app.about"));
     menu.append(Some("Open hosts file location"), Some("app.open-hosts"));
+    menu.append(Some("Method status"), Some("app.method-status"));
     menu.append(Some("Reset hosts file"), Some("app.reset-hosts"));
+    menu.append(Some("Undo hosts file reset"), Some("app.undo-reset-hosts"));
     menu
 }
 
 fn create_options_menu() -> Menu {
     let menu = Menu::new();
     menu.append(Some("Program settings"), Some("app.settings"));
+    menu.append(Some("Schedule…"), Some("app.schedule"));
     menu.append(Some("Custom splash art"), Some("app.custom-splash"));
     menu.append(
         Some("Auto-skip loading screen trailer"),
@@ -653,14 +739,32 @@ fn setup_menu_actions(app: &Application, window: &ApplicationWindow, app_state:
 
     // Open hosts location action
     let action = SimpleAction::new("open-hosts", None);
+    let app_state_clone = app_state.clone();
     action.connect_activate(move |_, _| {
-        // Open /etc directory in file manager
+        // Open the directory of the managed hosts file (normally /etc) in the file manager
+        let dir = std::path::Path::new(app_state_clone.hosts_manager.path())
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_else(|| std::path::PathBuf::from("/etc"));
         let _ = std::process::Command::new("xdg-open")
-            .arg("/etc")
+            .arg(dir)
             .spawn();
     });
     app.add_action(&action);
 
+    // Method status action
+    let action = SimpleAction::new("method-status", None);
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    action.connect_activate(move |_, _| {
+        let status = app_state_clone.backends.all().iter()
+            .map(|backend| format!("{}\n{}", backend.label(), backend.status().description))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        show_info_dialog(&window_clone, "Method status", &status);
+    });
+    app.add_action(&action);
+
     // Reset hosts action
     let action = SimpleAction::new("reset-hosts", None);
     let app_state_clone = app_state.clone();
@@ -670,6 +774,15 @@ fn setup_menu_actions(app: &Application, window: &ApplicationWindow, app_state:
     });
     app.add_action(&action);
 
+    // Undo hosts reset action
+    let action = SimpleAction::new("undo-reset-hosts", None);
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    action.connect_activate(move |_, _| {
+        undo_reset_hosts_action(&app_state_clone, &window_clone);
+    });
+    app.add_action(&action);
+
     // Program settings action
     let action = SimpleAction::new("settings", None);
     let app_state_clone = app_state.clone();
@@ -679,6 +792,15 @@ fn setup_menu_actions(app: &Application, window: &ApplicationWindow, app_state:
     });
     app.add_action(&action);
 
+    // Schedule action
+    let action = SimpleAction::new("schedule", None);
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    action.connect_activate(move |_, _| {
+        show_schedule_dialog(&app_state_clone, &window_clone);
+    });
+    app.add_action(&action);
+
     // Discord action
     let action = SimpleAction::new("discord", None);
     let discord_url = app_state.config.discord_url.clone();
@@ -920,6 +1042,79 @@ fn select_game_path<F: FnOnce(std::path::PathBuf) + 'static>(
     });
 }
 
+// Lets the user pick one of the installs found in the Steam libraries. Only asks when there is
+// more than one, finding none is reported so the folder can be browsed for instead.
+fn select_detected_game_path<F: FnOnce(game_install::GameInstall) + 'static>(
+    window: &ApplicationWindow,
+    on_selected: F,
+) {
+    let installs = game_install::find_game_installs();
+    match installs.as_slice() {
+        [] => {
+            show_info_dialog(
+                window,
+                "Game folder",
+                "Dead by Daylight wasn't found in any Steam library (native, Flatpak or Snap) or in the Heroic and Lutris settings.\n\nPlease select the folder with \"Browse…\" instead.",
+            );
+            return;
+        }
+        [install] => {
+            on_selected(install.clone());
+            return;
+        }
+        _ => {}
+    }
+
+    let dialog = Dialog::with_buttons(
+        Some("Game folder"),
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Select", ResponseType::Ok)],
+    );
+    dialog.set_default_response(ResponseType::Ok);
+
+    if let Some(action_area) = dialog.child().and_then(|c| c.last_child()) {
+        action_area.set_margin_start(15);
+        action_area.set_margin_end(15);
+        action_area.set_margin_top(10);
+        action_area.set_margin_bottom(15);
+    }
+
+    let content = dialog.content_area();
+    content.set_margin_start(15);
+    content.set_margin_end(15);
+    content.set_margin_top(10);
+    content.set_margin_bottom(10);
+    content.set_spacing(10);
+
+    let label = Label::new(Some("Dead by Daylight is installed in several places. Which one do you play?"));
+    label.set_wrap(true);
+    label.set_max_width_chars(45);
+    label.set_halign(gtk4::Align::Start);
+
+    let combo = ComboBoxText::new();
+    for install in &installs {
+        combo.append_text(&format!("{} ({})", install.path.display(), install.launcher.label()));
+    }
+    combo.set_active(Some(0));
+
+    content.append(&label);
+    content.append(&combo);
+
+    let on_selected = Rc::new(RefCell::new(Some(on_selected)));
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Ok {
+            let install = combo.active().and_then(|index| installs.get(index as usize));
+            if let (Some(install), Some(callback)) = (install, on_selected.borrow_mut().take()) {
+                callback(install.clone());
+            }
+        }
+        dialog.close();
+    });
+
+    dialog.show();
+}
+
 fn select_image_file<F: FnOnce(std::path::PathBuf) + 'static>(
     window: &ApplicationWindow,
     on_selected: F,
@@ -955,6 +1150,38 @@ fn select_image_file<F: FnOnce(std::path::PathBuf) + 'static>(
     });
 }
 
+fn select_json_file<F: FnOnce(std::path::PathBuf) + 'static>(
+    window: &ApplicationWindow,
+    on_selected: F,
+) {
+    let dialog = FileChooserNative::new(
+        Some("Select ip-ranges.json"),
+        Some(window),
+        FileChooserAction::Open,
+        Some("Open"),
+        Some("Cancel"),
+    );
+
+    let filter = FileFilter::new();
+    filter.add_mime_type("application/json");
+    filter.add_pattern("*.json");
+    dialog.add_filter(&filter);
+
+    let on_selected = Rc::new(RefCell::new(Some(on_selected)));
+    dialog.run_async(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(file) = dialog.file() {
+                if let Some(path) = file.path() {
+                    if let Some(callback) = on_selected.borrow_mut().take() {
+                        callback(path);
+                    }
+                }
+            }
+        }
+        dialog.destroy();
+    });
+}
+
 fn apply_custom_splash(game_path: &std::path::Path, image_path: &std::path::Path) -> anyhow::Result<()> {
     let pixbuf = gtk4::gdk_pixbuf::Pixbuf::from_file(image_path)?;
     if pixbuf.width() != 800 || pixbuf.height() != 450 {
@@ -1310,38 +1537,181 @@ fn show_about_dialog(app_state: &Rc<AppState>, window: &ApplicationWindow) {
     dialog.show();
 }
 
-fn reset_hosts_action(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+fn check_hosts_section(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    let issue = match app_state.hosts_manager.section_issue() {
+        Ok(Some(issue)) => issue,
+        _ => return,
+    };
+
     let dialog = MessageDialog::new(
         Some(window),
         gtk4::DialogFlags::MODAL,
         MessageType::Warning,
-        ButtonsType::YesNo,
-        "Restore Linux default hosts file",
+        ButtonsType::None,
+        "Hosts file was changed by another program",
+    );
+    dialog.set_secondary_text(Some(&issue.describe(app_state.hosts_manager.path())));
+    if issue == hosts::SectionIssue::Missing {
+        dialog.add_button("OK", ResponseType::Close);
+    } else {
+        dialog.add_button("Ignore", ResponseType::Close);
+        dialog.add_button("Repair", ResponseType::Ok);
+    }
+
+    let app_state = app_state.clone();
+    let window = window.clone();
+    dialog.run_async(move |dialog, response| {
+        dialog.close();
+        if response != ResponseType::Ok {
+            // Take the file as it is, so the same change isn't reported on every start
+            let _ = app_state.hosts_manager.accept_section();
+            return;
+        }
+
+        if let Some(obstacle) = app_state.hosts_manager.obstacle() {
+            show_hosts_obstacle_dialog(&app_state, &window, &obstacle);
+            return;
+        }
+
+        match app_state.hosts_manager.repair_section() {
+            Ok(_) => {
+                let message = with_cache_report(&app_state, "The Make Your Choice section was repaired.".to_string());
+                show_info_dialog(&window, "Repaired", &message);
+            }
+            Err(e) => show_error_dialog(&window, "Error", &e.to_string()),
+        }
+    });
+}
+
+fn reset_hosts_action(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    if let Some(obstacle) = app_state.hosts_manager.obstacle() {
+        show_hosts_obstacle_dialog(app_state, window, &obstacle);
+        return;
+    }
+
+    let (current, default_hosts) = match app_state.hosts_manager.current_content()
+        .and_then(|current| Ok((current, app_state.hosts_manager.default_content()?)))
+    {
+        Ok(contents) => contents,
+        Err(e) => {
+            show_error_dialog(window, "Error", &e.to_string());
+            return;
+        }
+    };
+
+    let dialog = Dialog::with_buttons(
+        Some("Restore Linux default hosts file"),
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        &[
+            ("Cancel", ResponseType::Cancel),
+            ("Reset", ResponseType::Ok),
+        ],
     );
-    dialog.set_secondary_text(Some(
+    dialog.set_default_width(700);
+    dialog.set_default_height(500);
+
+    let content = dialog.content_area();
+    let vbox = GtkBox::new(Orientation::Vertical, 10);
+    vbox.set_margin_start(15);
+    vbox.set_margin_end(15);
+    vbox.set_margin_top(15);
+    vbox.set_margin_bottom(15);
+
+    let distro = distro::Distro::detect();
+    let label = Label::new(Some(&format!(
         "If you are having problems, or the program doesn't seem to work correctly, try resetting your hosts file.\n\n\
-        This will overwrite your entire hosts file with the Linux default.\n\n\
-        A backup will be saved as hosts.bak. Continue?"
-    ));
+        This replaces your entire hosts file with the {} default shown below. \
+        The current file is backed up and can be put back with \"Undo hosts file reset\" in the version menu.",
+        distro.name()
+    )));
+    label.set_wrap(true);
+    label.set_halign(gtk4::Align::Start);
+    vbox.append(&label);
+
+    let hosts_path = app_state.hosts_manager.path();
+    let after_label = format!("{} (after reset)", hosts_path);
+    let diff = diff::unified_diff(hosts_path, &after_label, &current, &default_hosts);
+    let diff_text = if diff.is_empty() {
+        "The hosts file already matches the default.".to_string()
+    } else {
+        diff
+    };
+    vbox.append(&text_preview(&diff_text));
+    content.append(&vbox);
 
     let app_state = app_state.clone();
     let window = window.clone();
     dialog.run_async(move |dialog, response| {
-        if response == ResponseType::Yes {
+        dialog.close();
+        if response == ResponseType::Ok {
             match app_state.hosts_manager.restore_default() {
                 Ok(_) => {
-                    show_info_dialog(
-                        &window,
-                        "Success",
-                        "Hosts file restored to Linux default template.",
+                    let message = with_cache_report(
+                        &app_state,
+                        "Hosts file restored to the default template.\n\nUse \"Undo hosts file reset\" in the version menu to put the previous file back.".to_string(),
                     );
+                    show_info_dialog(&window, "Success", &message);
                 }
                 Err(e) => {
                     show_error_dialog(&window, "Error", &e.to_string());
                 }
             }
         }
+    });
+}
+
+fn undo_reset_hosts_action(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    if !app_state.hosts_manager.has_reset_backup() {
+        show_info_dialog(window, "Undo hosts file reset", "There is no hosts file reset to undo.");
+        return;
+    }
+    if let Some(obstacle) = app_state.hosts_manager.obstacle() {
+        show_hosts_obstacle_dialog(app_state, window, &obstacle);
+        return;
+    }
+
+    match app_state.hosts_manager.undo_restore_default() {
+        Ok(_) => {
+            let message = with_cache_report(app_state, "The hosts file from before the reset was put back.".to_string());
+            show_info_dialog(window, "Undo hosts file reset", &message);
+        }
+        Err(e) => show_error_dialog(window, "Error", &e.to_string()),
+    }
+}
+
+// Offers a method that works without the hosts file when it can't be written on this system
+fn show_hosts_obstacle_dialog(app_state: &Rc<AppState>, window: &ApplicationWindow, obstacle: &hosts_access::Obstacle) {
+    let alternative = hosts_access::alternative();
+    let label = app_state.backends.get(alternative).label();
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::None,
+        obstacle.title(),
+    );
+    dialog.set_secondary_text(Some(&obstacle.explanation(app_state.hosts_manager.path(), &distro::Distro::detect(), alternative)));
+    dialog.add_button("Close", ResponseType::Close);
+    dialog.add_button(&format!("Use {}", label), ResponseType::Ok);
+
+    let app_state = app_state.clone();
+    let window = window.clone();
+    dialog.run_async(move |dialog, response| {
         dialog.close();
+        if response == ResponseType::Ok {
+            {
+                let mut settings = app_state.settings.lock().unwrap();
+                settings.apply_mode = alternative;
+                let _ = settings.save();
+            }
+            show_info_dialog(
+                &window,
+                "Method changed",
+                &format!("\"{}\" is now selected in the settings. Press \"Apply Selection\" to apply your selection with it.", label),
+            );
+        }
     });
 }
 
@@ -1349,7 +1719,8 @@ fn show_conflict_dialog(
     window: &ApplicationWindow,
     app_state: &Rc<AppState>,
     selected: &HashSet<String>,
-    settings: &std::sync::MutexGuard<UserSettings>,
+    options: ApplyOptions,
+    on_applied: Option<AppliedCallback>,
 ) {
     let dialog = Dialog::with_buttons(
         Some("Conflicting Hosts Entries Detected"),
@@ -1402,9 +1773,6 @@ fn show_conflict_dialog(
     let app_state_clone = app_state.clone();
     let window_clone = window.clone();
     let selected_clone = selected.clone();
-    let apply_mode = settings.apply_mode;
-    let block_mode = settings.block_mode;
-    let merge_unstable = settings.merge_unstable;
 
     dialog.connect_response(move |dialog, response| {
         if response != ResponseType::Ok {
@@ -1431,17 +1799,28 @@ fn show_conflict_dialog(
             let app_state_clone2 = app_state_clone.clone();
             let window_clone2 = window_clone.clone();
             let selected_clone2 = selected_clone.clone();
+            let on_applied_clone2 = on_applied.clone();
 
             confirm_dialog.run_async(move |confirm_dialog, confirm_response| {
                 if confirm_response == ResponseType::Yes {
                     // User confirmed, proceed without clearing conflicts
-                    apply_hosts_changes(&app_state_clone2, &window_clone2, &selected_clone2, apply_mode, block_mode, merge_unstable);
+                    apply_hosts_changes(&app_state_clone2, &window_clone2, &selected_clone2, options, None, on_applied_clone2);
                 }
                 confirm_dialog.close();
             });
 
             dialog.close();
         } else {
+            // Taken before the cleanup, so a rollback brings the cleared entries back as well
+            let before = match app_state_clone.backends.snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    show_error_dialog(&window_clone, "Error", &format!("Failed to read the current state, nothing was changed:\n{}", e));
+                    dialog.close();
+                    return;
+                }
+            };
+
             // Clear conflicts first, then apply
             match app_state_clone.hosts_manager.detect_conflicting_entries(
                 &get_all_regions_map(&app_state_clone.regions, &app_state_clone.blocked_regions),
@@ -1461,7 +1840,7 @@ fn show_conflict_dialog(
             }
 
             // Conflicts cleared, now apply
-            apply_hosts_changes(&app_state_clone, &window_clone, &selected_clone, apply_mode, block_mode, merge_unstable);
+            apply_hosts_changes(&app_state_clone, &window_clone, &selected_clone, options, Some(before), on_applied.clone());
             dialog.close();
         }
     });
@@ -1469,100 +1848,847 @@ fn show_conflict_dialog(
     dialog.show();
 }
 
+// How far an apply got, reported to its AppliedCallback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ApplyEvent {
+    // The backends changed the system, the verification is still running
+    Changed,
+    // The verification proved the change wasn't in effect and undid it
+    RolledBack,
+    // The change went through and wasn't rolled back
+    Verified,
+}
+
+type AppliedCallback = Rc<dyn Fn(ApplyEvent)>;
+
+// `before` is the state from before changes made on the way to this apply, like clearing
+// conflicts, so rolling back undoes those as well
 fn apply_hosts_changes(
     app_state: &Rc<AppState>,
     window: &ApplicationWindow,
     selected: &HashSet<String>,
-    apply_mode: ApplyMode,
-    block_mode: BlockMode,
-    merge_unstable: bool,
+    options: ApplyOptions,
+    before: Option<Snapshot>,
+    on_applied: Option<AppliedCallback>,
 ) {
-    let result = match apply_mode {
-        ApplyMode::Gatekeep => app_state.hosts_manager.apply_gatekeep(
-            &app_state.regions,
-            &app_state.blocked_regions,
-            selected,
-            block_mode,
-            merge_unstable,
-        ),
-        ApplyMode::UniversalRedirect => {
-            if selected.len() != 1 {
-                show_error_dialog(
-                    window,
-                    "Universal Redirect",
-                    "Please select only one server when using Universal Redirect mode.",
-                );
-                return;
-            }
-            let region = selected.iter().next().unwrap();
-            app_state
-                .hosts_manager
-                .apply_universal_redirect(&app_state.regions, &app_state.blocked_regions, region)
-        }
+    let ApplyOptions { apply_mode, block_mode, merge_unstable } = options;
+    let (merge_with_stable, proxy_redirect) = {
+        let settings = app_state.settings.lock().unwrap();
+        (settings.merge_with_stable, settings.dns_proxy_redirect)
     };
+    let request = ApplyRequest {
+        regions: &app_state.regions,
+        blocked_regions: &app_state.blocked_regions,
+        selected,
+        block_mode,
+        merge_unstable,
+        merge_with_stable,
+        proxy_redirect,
+    };
+    let backend = app_state.backends.get(apply_mode);
+    // Drop what an earlier write (e.g. the refresh job) flushed, only this apply is reported
+    app_state.hosts_manager.take_flush_report();
+
+    // Planning first catches invalid selections before anything asks for a password
+    let result = app_state.backends.plan(apply_mode, &request).and_then(|plan| {
+        let expectations = backend.expectations(&request)?;
+        let snapshot = app_state.backends.apply(apply_mode, &request)?;
+        Ok((plan, expectations, before.clone().unwrap_or(snapshot)))
+    });
 
     match result {
-        Ok(_) => {
-            show_info_dialog(
-                window,
-                "Success",
-                &format!(
-                    "The hosts file was updated successfully ({:?} mode).\n\nPlease restart the game for changes to take effect.",
-                    apply_mode
-                ),
-            );
+        Ok((plan, expectations, snapshot)) => {
+            let message = format!("{}\n\n{}", plan.changes.join("\n"), backend.applied_message());
+            let message = with_cache_report(app_state, message);
+            if let Some(on_applied) = &on_applied {
+                on_applied(ApplyEvent::Changed);
+            }
+
+            let app_state_clone = app_state.clone();
+            let selected_clone = selected.clone();
+            let on_verified = move |event: ApplyEvent| {
+                if event == ApplyEvent::Verified {
+                    remember_redirect_region(&app_state_clone, apply_mode, &selected_clone);
+                }
+                if let Some(on_applied) = &on_applied {
+                    on_applied(event);
+                }
+            };
+            show_verification(app_state, window, apply_mode, snapshot, expectations, message, on_verified);
         }
         Err(e) => {
-            show_error_dialog(window, "Error", &e.to_string());
+            // The apply only rolled back to after the cleanup, the cleared entries come back here
+            let mut message = e.to_string();
+            if let Some(before) = &before {
+                match app_state.hosts_manager.restore(&before.hosts) {
+                    Ok(true) => message.push_str("\n\nThe cleared conflicting entries were put back."),
+                    Ok(false) => {}
+                    Err(e) => message.push_str(&format!("\n\nThe cleared conflicting entries could not be put back: {}", e)),
+                }
+            }
+            show_error_dialog(window, "Apply failed", &message);
         }
     }
 }
 
-fn handle_apply_click(app_state: &Rc<AppState>, window: &ApplicationWindow) {
-    let selected = app_state.selected_regions.borrow().clone();
-    let settings = app_state.settings.lock().unwrap();
-
-    // Check for conflicting entries before proceeding
-    match app_state.hosts_manager.detect_conflicting_entries(
-        &get_all_regions_map(&app_state.regions, &app_state.blocked_regions),
-    ) {
-        Ok(conflicts) if !conflicts.is_empty() => {
-            // Show conflict dialog and let it handle everything
-            show_conflict_dialog(window, app_state, &selected, &settings);
-            return;
-        }
-        Err(e) => {
-            show_error_dialog(window, "Error", &format!("Failed to check for conflicts:\n{}", e));
-            return;
-        }
-        _ => {} // No conflicts, continue
+// Adds which DNS caches the last hosts file write flushed, if it found any
+fn with_cache_report(app_state: &AppState, message: String) -> String {
+    match app_state.hosts_manager.take_flush_report().map(|report| report.describe()) {
+        Some(report) if !report.is_empty() => format!("{}\n\n{}", message, report),
+        _ => message,
     }
-
-    // No conflicts, apply directly
-    let apply_mode = settings.apply_mode;
-    let block_mode = settings.block_mode;
-    let merge_unstable = settings.merge_unstable;
-    drop(settings); // Release lock before applying
-
-    apply_hosts_changes(app_state, window, &selected, apply_mode, block_mode, merge_unstable);
 }
 
-fn handle_revert_click(app_state: &Rc<AppState>, window: &ApplicationWindow) {
-    match app_state.hosts_manager.revert() {
-        Ok(_) => {
-            show_info_dialog(
-                window,
-                "Reverted",
-                "Cleared Make Your Choice entries. Your existing hosts lines were left untouched.",
-            );
-        }
-        Err(e) => {
-            show_error_dialog(window, "Error", &e.to_string());
-        }
-    }
+// Remember the redirect target so the refresh job knows what to re-resolve
+fn remember_redirect_region(app_state: &AppState, apply_mode: ApplyMode, selected: &HashSet<String>) {
+    let mut settings = app_state.settings.lock().unwrap();
+    settings.redirect_region = if apply_mode == ApplyMode::UniversalRedirect {
+        selected.iter().next().cloned()
+    } else {
+        None
+    };
+    let _ = settings.save();
 }
 
-fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
+fn build_profile_row(app_state: &Rc<AppState>, window: &ApplicationWindow) -> GtkBox {
+    let row = GtkBox::new(Orientation::Horizontal, 6);
+    row.set_margin_start(10);
+    row.set_margin_end(10);
+    row.set_margin_bottom(5);
+
+    let label = Label::new(Some("Profile:"));
+    let combo = ComboBoxText::new();
+    combo.set_hexpand(true);
+    let btn_save = Button::with_label("Save…");
+    let btn_delete = Button::with_label("Delete");
+    btn_delete.set_sensitive(false);
+
+    let profiles = app_state.settings.lock().unwrap().profiles.clone();
+    fill_profile_combo(&combo, &profiles, None);
+
+    row.append(&label);
+    row.append(&combo);
+    row.append(&btn_save);
+    row.append(&btn_delete);
+
+    let app_state_clone = app_state.clone();
+    let btn_delete_clone = btn_delete.clone();
+    combo.connect_changed(move |combo| {
+        let name = combo.active_id();
+        btn_delete_clone.set_sensitive(name.is_some());
+        match name {
+            Some(name) => load_profile(&app_state_clone, &name),
+            None => unload_profile(&app_state_clone),
+        }
+    });
+
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    let combo_clone = combo.clone();
+    btn_save.connect_clicked(move |_| {
+        show_save_profile_dialog(&app_state_clone, &window_clone, &combo_clone);
+    });
+
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    btn_delete.connect_clicked(move |_| {
+        delete_profile(&app_state_clone, &window_clone, &combo);
+    });
+
+    row
+}
+
+// Must not be called with the settings locked, selecting the active entry loads the profile.
+// The first entry stands for no profile, profiles carry their name as id.
+fn fill_profile_combo(combo: &ComboBoxText, profiles: &[Profile], active: Option<&str>) {
+    combo.remove_all();
+    combo.append(None, NO_PROFILE);
+    for profile in profiles {
+        combo.append(Some(&profile.name), &profile.name);
+    }
+    let index = active
+        .and_then(|name| profiles.iter().position(|profile| profile.name == name))
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    combo.set_active(Some(index as u32));
+}
+
+// Ticks the profile's servers and applies with its options until another one is picked. The
+// saved settings are left alone, applying is left to the user.
+fn load_profile(app_state: &AppState, name: &str) {
+    let Some(profile) = app_state.settings.lock().unwrap().profile(name).cloned() else {
+        return;
+    };
+    *app_state.active_profile.borrow_mut() = Some(profile.name.clone());
+
+    set_checked_regions(app_state, &profile.selection());
+    refresh_warning_symbols(&app_state.list_store, &app_state.regions, profile.merge_unstable);
+}
+
+// Back to the options from the settings, the ticked servers stay
+fn unload_profile(app_state: &AppState) {
+    *app_state.active_profile.borrow_mut() = None;
+    let merge_unstable = app_state.settings.lock().unwrap().merge_unstable;
+    refresh_warning_symbols(&app_state.list_store, &app_state.regions, merge_unstable);
+}
+
+// The options the next apply uses: the loaded profile's, otherwise the saved ones
+fn current_apply_options(app_state: &AppState, settings: &UserSettings) -> ApplyOptions {
+    let active_profile = app_state.active_profile.borrow();
+    settings.apply_options(active_profile.as_deref().and_then(|name| settings.profile(name)))
+}
+
+fn show_save_profile_dialog(app_state: &Rc<AppState>, window: &ApplicationWindow, combo: &ComboBoxText) {
+    let mut servers: Vec<String> = app_state.selected_regions.borrow().iter().cloned().collect();
+    if servers.is_empty() {
+        show_error_dialog(window, "Save profile", "Please select at least one server to save in the profile.");
+        return;
+    }
+    servers.sort();
+
+    let dialog = Dialog::with_buttons(
+        Some("Save profile"),
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        &[("Cancel", ResponseType::Cancel), ("Save", ResponseType::Ok)],
+    );
+    dialog.set_default_width(380);
+    dialog.set_default_response(ResponseType::Ok);
+
+    if let Some(action_area) = dialog.child().and_then(|c| c.last_child()) {
+        action_area.set_margin_start(15);
+        action_area.set_margin_end(15);
+        action_area.set_margin_top(10);
+        action_area.set_margin_bottom(15);
+    }
+
+    let content = dialog.content_area();
+    content.set_margin_start(15);
+    content.set_margin_end(15);
+    content.set_margin_top(10);
+    content.set_margin_bottom(10);
+    content.set_spacing(10);
+
+    let options = current_apply_options(app_state, &app_state.settings.lock().unwrap());
+    let description = Label::new(Some(&format!(
+        "Saves {} with the current method ({}) and Gatekeep options. Saving under an existing name replaces that profile.",
+        servers.join(", "),
+        app_state.backends.get(options.apply_mode).label()
+    )));
+    description.set_wrap(true);
+    description.set_max_width_chars(45);
+    description.set_halign(gtk4::Align::Start);
+
+    let entry = Entry::new();
+    entry.set_placeholder_text(Some("Name, e.g. EU duo queue"));
+    entry.set_activates_default(true);
+    if let Some(name) = combo.active_id() {
+        entry.set_text(&name);
+    }
+
+    content.append(&description);
+    content.append(&entry);
+
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    let combo_clone = combo.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response != ResponseType::Ok {
+            dialog.close();
+            return;
+        }
+
+        let name = entry.text().trim().to_string();
+        if name.is_empty() {
+            show_error_dialog(&window_clone, "Save profile", "Please enter a name for the profile.");
+            return;
+        }
+
+        let profile = Profile {
+            name: name.clone(),
+            selected: servers.clone(),
+            apply_mode: options.apply_mode,
+            block_mode: options.block_mode,
+            merge_unstable: options.merge_unstable,
+        };
+        let (saved, profiles) = {
+            let mut settings = app_state_clone.settings.lock().unwrap();
+            settings.save_profile(profile);
+            (settings.save(), settings.profiles.clone())
+        };
+
+        dialog.close();
+        fill_profile_combo(&combo_clone, &profiles, Some(&name));
+        if let Err(e) = saved {
+            show_error_dialog(&window_clone, "Save profile", &format!("Failed to save the profile:\n{}", e));
+        }
+    });
+
+    dialog.show();
+}
+
+fn delete_profile(app_state: &Rc<AppState>, window: &ApplicationWindow, combo: &ComboBoxText) {
+    let Some(name) = combo.active_id() else {
+        return;
+    };
+
+    let dialog = MessageDialog::new(
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Question,
+        ButtonsType::YesNo,
+        "Delete profile",
+    );
+    dialog.set_secondary_text(Some(&format!(
+        "Delete the profile \"{}\"? The ticked servers and the hosts file stay as they are.",
+        name
+    )));
+
+    let app_state = app_state.clone();
+    let combo = combo.clone();
+    dialog.run_async(move |dialog, response| {
+        if response == ResponseType::Yes {
+            let profiles = {
+                let mut settings = app_state.settings.lock().unwrap();
+                settings.remove_profile(&name);
+                let _ = settings.save();
+                settings.profiles.clone()
+            };
+            fill_profile_combo(&combo, &profiles, None);
+        }
+        dialog.close();
+    });
+}
+
+fn show_schedule_dialog(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    let dialog = Dialog::with_buttons(
+        Some("Schedule"),
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        &[("Close", ResponseType::Close)],
+    );
+    dialog.set_default_width(420);
+
+    if let Some(action_area) = dialog.child().and_then(|c| c.last_child()) {
+        action_area.set_margin_start(15);
+        action_area.set_margin_end(15);
+        action_area.set_margin_top(10);
+        action_area.set_margin_bottom(15);
+    }
+
+    let content = dialog.content_area();
+    content.set_margin_start(15);
+    content.set_margin_end(15);
+    content.set_margin_top(10);
+    content.set_margin_bottom(10);
+    content.set_spacing(10);
+
+    let description = Label::new(Some(
+        "Applies a saved profile at the given time, e.g. a low-latency profile in the evening and a wider selection otherwise.",
+    ));
+    description.set_wrap(true);
+    description.set_max_width_chars(50);
+    description.set_halign(gtk4::Align::Start);
+
+    let list = GtkBox::new(Orientation::Vertical, 5);
+    fill_schedule_list(&list, app_state);
+
+    // Time, days and profile of a new schedule
+    let add_row = GtkBox::new(Orientation::Horizontal, 6);
+    let time_entry = Entry::new();
+    time_entry.set_placeholder_text(Some("19:00"));
+    time_entry.set_width_chars(6);
+    let days_combo = ComboBoxText::new();
+    for (label, _) in SCHEDULE_DAYS {
+        days_combo.append_text(label);
+    }
+    days_combo.set_active(Some(0));
+    let profile_combo = ComboBoxText::new();
+    profile_combo.set_hexpand(true);
+    for profile in &app_state.settings.lock().unwrap().profiles {
+        profile_combo.append_text(&profile.name);
+    }
+    profile_combo.set_active(Some(0));
+    let btn_add = Button::with_label("Add");
+
+    add_row.append(&time_entry);
+    add_row.append(&days_combo);
+    add_row.append(&profile_combo);
+    add_row.append(&btn_add);
+
+    let timers_check = CheckButton::with_label("Also run with systemd user timers (works while the app is closed)");
+    timers_check.set_active(app_state.settings.lock().unwrap().schedule_timers);
+    let timers_hint = Label::new(Some(
+        "Timers can't ask for your password. They only work for profiles using the DNS proxy, or a hosts file your user can write to.",
+    ));
+    timers_hint.set_wrap(true);
+    timers_hint.set_max_width_chars(50);
+    timers_hint.set_halign(gtk4::Align::Start);
+    timers_hint.add_css_class("dim-label");
+
+    content.append(&description);
+    content.append(&list);
+    content.append(&add_row);
+    content.append(&timers_check);
+    content.append(&timers_hint);
+
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    btn_add.connect_clicked(move |_| {
+        let Some(profile) = profile_combo.active_text() else {
+            show_error_dialog(&window_clone, "Schedule", "Save a profile first, schedules switch between saved profiles.");
+            return;
+        };
+        let days = SCHEDULE_DAYS[days_combo.active().unwrap_or(0) as usize].1;
+        let schedule = match schedule::Schedule::new(&profile, &time_entry.text(), days) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                show_error_dialog(&window_clone, "Schedule", &format!("{}", e));
+                return;
+            }
+        };
+
+        {
+            let mut settings = app_state_clone.settings.lock().unwrap();
+            settings.schedules.push(schedule);
+            let _ = settings.save();
+        }
+        time_entry.set_text("");
+        fill_schedule_list(&list, &app_state_clone);
+    });
+
+    // The timers are (re)written on close, so they match the final list
+    let app_state_clone = app_state.clone();
+    let window_clone = window.clone();
+    dialog.connect_response(move |dialog, _| {
+        let use_timers = timers_check.is_active();
+        let (schedules, was_using_timers, unattended) = {
+            let settings = app_state_clone.settings.lock().unwrap();
+            let unattended = schedule::check_unattended(&settings.schedules, &settings, &app_state_clone.backends);
+            (settings.schedules.clone(), settings.schedule_timers, unattended)
+        };
+
+        // Timers that would fail at pkexec aren't installed, the app keeps switching instead
+        let result = if use_timers && !schedules.is_empty() {
+            match unattended {
+                Ok(()) => std::env::current_exe()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|exe| schedule::install_timers(&schedules, &exe, None)),
+                Err(e) => schedule::remove_timers().and(Err(e)),
+            }
+        } else if was_using_timers || schedule::timers_installed() {
+            schedule::remove_timers()
+        } else {
+            Ok(())
+        };
+
+        {
+            let mut settings = app_state_clone.settings.lock().unwrap();
+            settings.schedule_timers = use_timers && result.is_ok();
+            let _ = settings.save();
+        }
+
+        dialog.close();
+        if let Err(e) = result {
+            show_error_dialog(&window_clone, "Schedule", &format!("Failed to update the systemd user timers:\n{:#}", e));
+        }
+    });
+
+    dialog.show();
+}
+
+// Day choices for a new schedule, the presets first
+const SCHEDULE_DAYS: &[(&str, &[chrono::Weekday])] = &[
+    ("Every day", schedule::EVERY_DAY),
+    ("Weekdays", schedule::WEEKDAYS),
+    ("Weekends", schedule::WEEKENDS),
+    ("Mondays", &[chrono::Weekday::Mon]),
+    ("Tuesdays", &[chrono::Weekday::Tue]),
+    ("Wednesdays", &[chrono::Weekday::Wed]),
+    ("Thursdays", &[chrono::Weekday::Thu]),
+    ("Fridays", &[chrono::Weekday::Fri]),
+    ("Saturdays", &[chrono::Weekday::Sat]),
+    ("Sundays", &[chrono::Weekday::Sun]),
+];
+
+fn fill_schedule_list(list: &GtkBox, app_state: &Rc<AppState>) {
+    while let Some(child) = list.first_child() {
+        list.remove(&child);
+    }
+
+    let settings = app_state.settings.lock().unwrap().clone();
+    if settings.schedules.is_empty() {
+        let label = Label::new(Some("No schedules yet."));
+        label.set_halign(gtk4::Align::Start);
+        list.append(&label);
+        return;
+    }
+
+    for (index, entry) in settings.schedules.iter().enumerate() {
+        let row = GtkBox::new(Orientation::Horizontal, 6);
+        let text = if settings.profile(&entry.profile).is_some() {
+            entry.describe()
+        } else {
+            format!("{} ⚠︎ profile missing", entry.describe())
+        };
+        let label = Label::new(Some(&text));
+        label.set_hexpand(true);
+        label.set_halign(gtk4::Align::Start);
+        let btn_remove = Button::with_label("Remove");
+
+        row.append(&label);
+        row.append(&btn_remove);
+        list.append(&row);
+
+        let list_clone = list.clone();
+        let app_state_clone = app_state.clone();
+        btn_remove.connect_clicked(move |_| {
+            {
+                let mut settings = app_state_clone.settings.lock().unwrap();
+                if index < settings.schedules.len() {
+                    settings.schedules.remove(index);
+                }
+                let _ = settings.save();
+            }
+            fill_schedule_list(&list_clone, &app_state_clone);
+        });
+    }
+}
+
+// Checks every 30 seconds whether a schedule fired since the last check. Only schedules that
+// fire while the app is open count, nothing is applied on startup.
+fn start_schedule_timer(app_state: Rc<AppState>, window: &ApplicationWindow) {
+    let window = window.clone();
+    let mut last_check = chrono::Local::now().naive_local();
+    glib::timeout_add_seconds_local(30, move || {
+        let now = chrono::Local::now().naive_local();
+        let since = std::mem::replace(&mut last_check, now);
+
+        let (name, profile) = {
+            let settings = app_state.settings.lock().unwrap();
+            // The systemd timers switch on their own, don't apply twice
+            if settings.schedule_timers {
+                return glib::ControlFlow::Continue;
+            }
+            match schedule::due(&settings.schedules, since, now) {
+                Some(due) => (due.profile.clone(), settings.profile(&due.profile).cloned()),
+                None => return glib::ControlFlow::Continue,
+            }
+        };
+
+        let Some(profile) = profile else {
+            show_error_dialog(
+                &window,
+                "Schedule",
+                &format!("The scheduled profile \"{}\" no longer exists. Remove the schedule in Options → Schedule.", name),
+            );
+            return glib::ControlFlow::Continue;
+        };
+
+        // Applied with the profile's options only, the saved settings and the profile row stay
+        let options = app_state.settings.lock().unwrap().apply_options(Some(&profile));
+        apply_selection(&app_state, &window, &profile.selection(), options, None);
+        glib::ControlFlow::Continue
+    });
+}
+
+// Applies the session selection when the game process appears and restores what was in
+// place before when it is gone. Only a session this watcher applied successfully is undone,
+// so enabling it mid-game or a cancelled apply leaves things alone.
+fn start_game_session_watcher(app_state: Rc<AppState>, window: &ApplicationWindow) {
+    let window = window.clone();
+    let mut watcher = game_session::GameWatcher::new();
+    // State before the session and the redirect target then, set as soon as the session's apply
+    // changed something, so an exit during the verification still restores it
+    let session: Rc<RefCell<Option<(Snapshot, Option<String>)>>> = Rc::new(RefCell::new(None));
+    glib::timeout_add_seconds_local(game_session::POLL_INTERVAL_SECS, move || {
+        let event = watcher.poll();
+        let (enabled, profile_name) = {
+            let settings = app_state.settings.lock().unwrap();
+            (settings.game_session, settings.game_session_profile.clone())
+        };
+
+        match event {
+            Some(game_session::GameEvent::Started) if enabled => {
+                let (selected, options, redirect_region) = {
+                    let settings = app_state.settings.lock().unwrap();
+                    let (selected, options) = match &profile_name {
+                        Some(name) => match settings.profile(name) {
+                            Some(profile) => (profile.selection(), settings.apply_options(Some(profile))),
+                            None => {
+                                show_error_dialog(
+                                    &window,
+                                    "Game session",
+                                    &format!("The game session profile \"{}\" no longer exists. Pick another one in Options → Program settings.", name),
+                                );
+                                return glib::ControlFlow::Continue;
+                            }
+                        },
+                        None => (app_state.selected_regions.borrow().clone(), current_apply_options(&app_state, &settings)),
+                    };
+                    (selected, options, settings.redirect_region.clone())
+                };
+                if selected.is_empty() {
+                    return glib::ControlFlow::Continue;
+                }
+
+                let snapshot = match app_state.backends.snapshot() {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => {
+                        show_error_dialog(&window, "Game session", &format!("Failed to read the current state, nothing was applied:\n{}", e));
+                        return glib::ControlFlow::Continue;
+                    }
+                };
+                let pending = RefCell::new(Some((snapshot, redirect_region)));
+                let session_clone = session.clone();
+                let on_applied: AppliedCallback = Rc::new(move |event: ApplyEvent| match event {
+                    ApplyEvent::Changed => {
+                        if let Some(before) = pending.borrow_mut().take() {
+                            *session_clone.borrow_mut() = Some(before);
+                        }
+                    }
+                    // Already back to the state before the session, nothing to restore on exit
+                    ApplyEvent::RolledBack => {
+                        *session_clone.borrow_mut() = None;
+                    }
+                    ApplyEvent::Verified => {}
+                });
+                apply_selection(&app_state, &window, &selected, options, Some(on_applied));
+            }
+            Some(game_session::GameEvent::Exited) => {
+                let Some((snapshot, redirect_region)) = session.borrow_mut().take() else {
+                    return glib::ControlFlow::Continue;
+                };
+                app_state.hosts_manager.take_flush_report();
+                let restored = app_state.backends.rollback(&snapshot);
+                {
+                    let mut settings = app_state.settings.lock().unwrap();
+                    settings.redirect_region = redirect_region;
+                    let _ = settings.save();
+                }
+                let message = with_cache_report(&app_state, format!("The game exited, the state from before the session is back:\n{}", restored));
+                show_info_dialog(&window, "Game session ended", &message);
+            }
+            _ => {}
+        }
+
+        glib::ControlFlow::Continue
+    });
+}
+
+fn handle_apply_click(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    let selected = app_state.selected_regions.borrow().clone();
+    let options = current_apply_options(app_state, &app_state.settings.lock().unwrap());
+
+    // Applied with the saved options, the servers are remembered for game sessions without a profile
+    let on_applied: Option<AppliedCallback> = if app_state.active_profile.borrow().is_none() {
+        let app_state_clone = app_state.clone();
+        let selected_clone = selected.clone();
+        Some(Rc::new(move |event: ApplyEvent| {
+            if event == ApplyEvent::Verified {
+                let mut settings = app_state_clone.settings.lock().unwrap();
+                settings.remember_selection(&selected_clone);
+                let _ = settings.save();
+            }
+        }))
+    } else {
+        None
+    };
+    apply_selection(app_state, window, &selected, options, on_applied);
+}
+
+// Applies `selected` with `options` after checking the hosts file for obstacles and conflicts
+fn apply_selection(
+    app_state: &Rc<AppState>,
+    window: &ApplicationWindow,
+    selected: &HashSet<String>,
+    options: ApplyOptions,
+    on_applied: Option<AppliedCallback>,
+) {
+    // Explain an unwritable hosts file before pkexec asks for a password
+    if app_state.backends.get(options.apply_mode).uses_hosts_file() {
+        if let Some(obstacle) = app_state.hosts_manager.obstacle() {
+            show_hosts_obstacle_dialog(app_state, window, &obstacle);
+            return;
+        }
+    }
+
+    // Check for conflicting entries before proceeding
+    match app_state.hosts_manager.detect_conflicting_entries(
+        &get_all_regions_map(&app_state.regions, &app_state.blocked_regions),
+    ) {
+        Ok(conflicts) if !conflicts.is_empty() => {
+            // Show conflict dialog and let it handle everything
+            show_conflict_dialog(window, app_state, selected, options, on_applied);
+            return;
+        }
+        Err(e) => {
+            show_error_dialog(window, "Error", &format!("Failed to check for conflicts:\n{}", e));
+            return;
+        }
+        _ => {} // No conflicts, continue
+    }
+
+    // No conflicts, apply directly
+    apply_hosts_changes(app_state, window, selected, options, None, on_applied);
+}
+
+// Checks in the background that the system resolver now answers as expected and reports it
+// together with the success message. Answers that prove the change isn't in effect roll it back.
+// `on_verified` learns whether the change was kept or rolled back.
+fn show_verification(
+    app_state: &Rc<AppState>,
+    window: &ApplicationWindow,
+    apply_mode: ApplyMode,
+    snapshot: Snapshot,
+    expectations: Vec<Expectation>,
+    message: String,
+    on_verified: impl FnOnce(ApplyEvent) + 'static,
+) {
+    if expectations.is_empty() {
+        on_verified(ApplyEvent::Verified);
+        show_info_dialog(window, "Success", &message);
+        return;
+    }
+
+    let uses_hosts_file = app_state.backends.get(apply_mode).uses_hosts_file();
+    let runtime = app_state.tokio_runtime.clone();
+    let app_state = app_state.clone();
+    let window = window.clone();
+    glib::spawn_future_local(async move {
+        let report = runtime
+            .spawn_blocking(move || verify::verify(&expectations, uses_hosts_file))
+            .await
+            .unwrap();
+
+        if report.contradicted() {
+            let restored = app_state.backends.rollback(&snapshot);
+            on_verified(ApplyEvent::RolledBack);
+            show_error_dialog(
+                &window,
+                "Apply rolled back",
+                &format!("{}\n\nThe change was rolled back:\n{}", report.describe(), restored),
+            );
+            return;
+        }
+
+        on_verified(ApplyEvent::Verified);
+        let message = format!("{}\n\n{}", message, report.describe());
+        if report.all_ok() {
+            show_info_dialog(&window, "Success", &message);
+        } else {
+            show_warning_dialog(&window, "Applied, but some lookups failed", &message);
+        }
+    });
+}
+
+fn show_preview_dialog(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    let selected = app_state.selected_regions.borrow().clone();
+    let settings = app_state.settings.lock().unwrap().clone();
+    let options = current_apply_options(app_state, &settings);
+    let request = ApplyRequest {
+        regions: &app_state.regions,
+        blocked_regions: &app_state.blocked_regions,
+        selected: &selected,
+        block_mode: options.block_mode,
+        merge_unstable: options.merge_unstable,
+        merge_with_stable: settings.merge_with_stable,
+        proxy_redirect: settings.dns_proxy_redirect,
+    };
+
+    let plan = match app_state.backends.plan(options.apply_mode, &request) {
+        Ok(plan) => plan,
+        Err(e) => {
+            show_error_dialog(window, "Preview changes", &e.to_string());
+            return;
+        }
+    };
+
+    let dialog = Dialog::with_buttons(
+        Some("Preview changes"),
+        Some(window),
+        gtk4::DialogFlags::MODAL,
+        &[
+            ("Close", ResponseType::Close),
+            ("Apply Selection", ResponseType::Ok),
+        ],
+    );
+    dialog.set_default_width(700);
+    dialog.set_default_height(500);
+
+    let content = dialog.content_area();
+    let vbox = GtkBox::new(Orientation::Vertical, 10);
+    vbox.set_margin_start(15);
+    vbox.set_margin_end(15);
+    vbox.set_margin_top(15);
+    vbox.set_margin_bottom(15);
+
+    let summary = Label::new(Some(&plan.changes.join("\n")));
+    summary.set_wrap(true);
+    summary.set_halign(gtk4::Align::Start);
+    vbox.append(&summary);
+
+    let diffs: Vec<String> = plan.files.iter()
+        .map(|file| file.diff())
+        .filter(|diff| !diff.is_empty())
+        .collect();
+    let diff_text = if diffs.is_empty() {
+        "No files change.".to_string()
+    } else {
+        diffs.join("\n")
+    };
+
+    vbox.append(&text_preview(&diff_text));
+    content.append(&vbox);
+
+    let app_state = app_state.clone();
+    let window = window.clone();
+    dialog.run_async(move |dialog, response| {
+        dialog.close();
+        if response == ResponseType::Ok {
+            handle_apply_click(&app_state, &window);
+        }
+    });
+}
+
+// Read-only monospace view for diffs and file contents
+fn text_preview(text: &str) -> ScrolledWindow {
+    let text_view = TextView::new();
+    text_view.set_editable(false);
+    text_view.set_monospace(true);
+    text_view.buffer().set_text(text);
+
+    let scrolled = ScrolledWindow::new();
+    scrolled.set_vexpand(true);
+    scrolled.set_child(Some(&text_view));
+    scrolled
+}
+
+fn handle_revert_click(app_state: &Rc<AppState>, window: &ApplicationWindow) {
+    app_state.hosts_manager.take_flush_report();
+    let result = app_state.backends.revert_all();
+
+    match result {
+        Ok(_) => {
+            {
+                let mut settings = app_state.settings.lock().unwrap();
+                settings.redirect_region = None;
+                let _ = settings.save();
+            }
+
+            let message = with_cache_report(
+                app_state,
+                "Cleared Make Your Choice entries, firewall rules, DNS proxy and systemd-resolved configuration. Your existing hosts lines were left untouched.".to_string(),
+            );
+            show_info_dialog(window, "Reverted", &message);
+        }
+        Err(e) => {
+            show_error_dialog(window, "Error", &e.to_string());
+        }
+    }
+}
+
+fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
     let dialog = Dialog::with_buttons(
         Some("Program Settings"),
         Some(parent),
@@ -1593,8 +2719,9 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
     let mode_label = Label::new(Some("Method:"));
     mode_label.set_halign(gtk4::Align::Start);
     let mode_combo = ComboBoxText::new();
-    mode_combo.append_text("Gatekeep (default)");
-    mode_combo.append_text("Universal Redirect (deprecated)");
+    for backend in app_state.backends.all() {
+        mode_combo.append_text(backend.label());
+    }
 
     let mode_notice = Label::new(Some(
         "After changing this setting, reapply your selection to apply changes.",
@@ -1604,10 +2731,17 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
     mode_notice.set_halign(gtk4::Align::Start);
 
     let settings = app_state.settings.lock().unwrap();
-    mode_combo.set_active(Some(match settings.apply_mode {
-        ApplyMode::Gatekeep => 0,
-        ApplyMode::UniversalRedirect => 1,
-    }));
+    let mode_index = app_state.backends.all().iter()
+        .position(|backend| backend.mode() == settings.apply_mode)
+        .unwrap_or(0);
+    mode_combo.set_active(Some(mode_index as u32));
+
+    // Universal Redirect pins IPs that AWS rotates every few weeks
+    let refresh_check = CheckButton::with_label("Keep Universal Redirect IPs up to date");
+    refresh_check.set_active(settings.refresh_redirect_ips);
+
+    let proxy_redirect_check = CheckButton::with_label("DNS proxy: redirect to the selected server instead of blocking");
+    proxy_redirect_check.set_active(settings.dns_proxy_redirect);
 
     // Block mode - using CheckButtons in radio mode
     let block_label = Label::new(Some("Gatekeep Options:"));
@@ -1629,16 +2763,77 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
     // Merge unstable
     let merge_check = CheckButton::with_label("Merge unstable servers (recommended)");
     merge_check.set_active(settings.merge_unstable);
+    let merge_with_stable_check = CheckButton::with_label("Also merge when stable servers are selected");
+    merge_with_stable_check.set_active(settings.merge_with_stable);
+    merge_with_stable_check.set_sensitive(settings.merge_unstable);
+    merge_with_stable_check.set_margin_start(20);
+    let merge_with_stable_for_toggle = merge_with_stable_check.clone();
+    merge_check.connect_toggled(move |check| {
+        merge_with_stable_for_toggle.set_sensitive(check.is_active());
+    });
+
+    // Game session
+    let session_check = CheckButton::with_label("Apply when Dead by Daylight starts, revert when it exits");
+    session_check.set_active(settings.game_session);
+    let session_combo = ComboBoxText::new();
+    session_combo.append_text("Servers ticked in the app");
+    for profile in &settings.profiles {
+        session_combo.append_text(&profile.name);
+    }
+    let session_index = settings.game_session_profile.as_ref()
+        .and_then(|name| settings.profiles.iter().position(|profile| &profile.name == name))
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    session_combo.set_active(Some(session_index as u32));
+    session_combo.set_sensitive(settings.game_session);
+    session_combo.set_margin_start(20);
+    let session_combo_for_toggle = session_combo.clone();
+    session_check.connect_toggled(move |check| {
+        session_combo_for_toggle.set_sensitive(check.is_active());
+    });
 
     settings_box.append(&mode_label);
     settings_box.append(&mode_combo);
     settings_box.append(&mode_notice);
+    settings_box.append(&refresh_check);
+    settings_box.append(&proxy_redirect_check);
+    settings_box.append(&Separator::new(Orientation::Horizontal));
+
+    // AWS IP ranges used by Firewall mode
+    let ip_ranges_label = Label::new(Some("Firewall IP ranges:"));
+    ip_ranges_label.set_halign(gtk4::Align::Start);
+    let ip_ranges_status = Label::new(Some(&describe_ip_ranges_cache()));
+    ip_ranges_status.set_wrap(true);
+    ip_ranges_status.set_max_width_chars(40);
+    ip_ranges_status.set_halign(gtk4::Align::Start);
+    let ip_ranges_download = Button::with_label("Download latest");
+    let ip_ranges_import = Button::with_label("Import file…");
+
+    let ip_ranges_row = GtkBox::new(Orientation::Horizontal, 6);
+    ip_ranges_row.append(&ip_ranges_download);
+    ip_ranges_row.append(&ip_ranges_import);
+
+    let ip_ranges_hint = Label::new(Some(
+        "Firewall mode blocks the EC2 IP ranges of unselected servers, as published by AWS in ip-ranges.json. This includes other services hosted on EC2 in those regions.",
+    ));
+    ip_ranges_hint.set_wrap(true);
+    ip_ranges_hint.set_max_width_chars(40);
+    ip_ranges_hint.set_halign(gtk4::Align::Start);
+
+    settings_box.append(&ip_ranges_label);
+    settings_box.append(&ip_ranges_status);
+    settings_box.append(&ip_ranges_row);
+    settings_box.append(&ip_ranges_hint);
     settings_box.append(&Separator::new(Orientation::Horizontal));
     settings_box.append(&block_label);
     settings_box.append(&rb_both);
     settings_box.append(&rb_ping);
     settings_box.append(&rb_service);
     settings_box.append(&merge_check);
+    settings_box.append(&merge_with_stable_check);
+    settings_box.append(&Separator::new(Orientation::Horizontal));
+    settings_box.append(&session_check);
+    settings_box.append(&session_combo);
     settings_box.append(&Separator::new(Orientation::Horizontal));
 
     // Game folder
@@ -1646,26 +2841,56 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
     game_path_label.set_halign(gtk4::Align::Start);
     let game_path_entry = Entry::new();
     game_path_entry.set_hexpand(true);
+    let detect_button = Button::with_label("Detect");
     let browse_button = Button::with_label("Browse…");
 
     let game_path_row = GtkBox::new(Orientation::Horizontal, 6);
     game_path_row.append(&game_path_entry);
+    game_path_row.append(&detect_button);
     game_path_row.append(&browse_button);
 
+    let game_launcher_label = Label::new(None);
+    game_launcher_label.set_halign(gtk4::Align::Start);
+
     let hint_label = Label::new(Some(
-        "Tip: In Steam, right-click Dead by Daylight → Manage → Browse local files.\nThe folder that opens is the one you should select.\n\nThis setting is only required for some features like custom splash art and auto-skip trailer.",
+        "\"Detect\" looks for the game in your Steam libraries and in Heroic and Lutris. Otherwise, in Steam, right-click Dead by Daylight → Manage → Browse local files.\nThe folder that opens is the one you should select.\n\nThis setting is only required for some features like custom splash art and auto-skip trailer.",
     ));
     hint_label.set_wrap(true);
     hint_label.set_max_width_chars(40);
     hint_label.set_halign(gtk4::Align::Start);
 
     game_path_entry.set_text(&settings.game_path);
+    if !settings.game_path.is_empty() {
+        game_launcher_label.set_text(&format!("Installed with: {}", settings.game_launcher.label()));
+    }
+
+    // Hosts file, for containers and Wine or Proton prefixes
+    let hosts_path_label = Label::new(Some("Hosts file:"));
+    hosts_path_label.set_halign(gtk4::Align::Start);
+    let hosts_path_entry = Entry::new();
+    hosts_path_entry.set_hexpand(true);
+    hosts_path_entry.set_placeholder_text(Some(hosts::HOSTS_PATH));
+    hosts_path_entry.set_text(settings.hosts_path.as_deref().unwrap_or(""));
     drop(settings);
 
+    let hosts_path_hint = Label::new(Some(&format!(
+        "Leave empty for {}. Another file, e.g. drive_c/windows/system32/drivers/etc/hosts in a Wine prefix, \
+        is changed without asking for a password if you can write it. Takes effect after restarting the app.",
+        hosts::HOSTS_PATH
+    )));
+    hosts_path_hint.set_wrap(true);
+    hosts_path_hint.set_max_width_chars(40);
+    hosts_path_hint.set_halign(gtk4::Align::Start);
+
     settings_box.append(&game_path_label);
     settings_box.append(&game_path_row);
+    settings_box.append(&game_launcher_label);
     settings_box.append(&hint_label);
     settings_box.append(&Separator::new(Orientation::Horizontal));
+    settings_box.append(&hosts_path_label);
+    settings_box.append(&hosts_path_entry);
+    settings_box.append(&hosts_path_hint);
+    settings_box.append(&Separator::new(Orientation::Horizontal));
 
     // Tip label
     let tip_label = Label::new(Some(
@@ -1679,22 +2904,83 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
 
     content.append(&settings_box);
 
+    let parent_clone = parent.clone();
+    let game_path_entry_for_detect = game_path_entry.clone();
+    let game_launcher_label_for_detect = game_launcher_label.clone();
+    detect_button.connect_clicked(move |_| {
+        let entry_clone = game_path_entry_for_detect.clone();
+        let launcher_label_clone = game_launcher_label_for_detect.clone();
+        select_detected_game_path(&parent_clone, move |install| {
+            entry_clone.set_text(install.path.to_string_lossy().as_ref());
+            launcher_label_clone.set_text(&format!("Installed with: {}", install.launcher.label()));
+        });
+    });
+
     let parent_clone = parent.clone();
     let game_path_entry_for_browse = game_path_entry.clone();
+    let game_launcher_label_for_browse = game_launcher_label.clone();
     browse_button.connect_clicked(move |_| {
         let entry_clone = game_path_entry_for_browse.clone();
+        let launcher_label_clone = game_launcher_label_for_browse.clone();
         let parent_for_dialog = parent_clone.clone();
         let parent_for_error = parent_clone.clone();
         select_game_path(&parent_for_dialog, move |path| {
-            if !is_valid_game_folder(&path) {
+            // A folder inside the install, e.g. DeadByDaylight/Binaries, is accepted too
+            let Some(path) = game_install::game_folder_of(&path) else {
                 show_error_dialog(
                     &parent_for_error,
                     "Invalid game folder",
-                    "Please select the folder named \"Dead by Daylight\".",
+                    INVALID_GAME_FOLDER,
                 );
                 return;
-            }
+            };
             entry_clone.set_text(path.to_string_lossy().as_ref());
+            launcher_label_clone.set_text(&format!("Installed with: {}", game_install::launcher_for(&path).label()));
+        });
+    });
+
+    let parent_clone = parent.clone();
+    let status_for_import = ip_ranges_status.clone();
+    ip_ranges_import.connect_clicked(move |_| {
+        let status_clone = status_for_import.clone();
+        let parent_for_error = parent_clone.clone();
+        select_json_file(&parent_clone, move |path| {
+            match IpRanges::import(&path) {
+                Ok(_) => status_clone.set_text(&describe_ip_ranges_cache()),
+                Err(e) => show_error_dialog(
+                    &parent_for_error,
+                    "IP ranges",
+                    &format!("Failed to import IP ranges:\n{:#}", e),
+                ),
+            }
+        });
+    });
+
+    let parent_clone = parent.clone();
+    let runtime = app_state.tokio_runtime.clone();
+    ip_ranges_download.connect_clicked(move |button| {
+        let button = button.clone();
+        let status_clone = ip_ranges_status.clone();
+        let parent_for_error = parent_clone.clone();
+        let runtime = runtime.clone();
+        button.set_sensitive(false);
+
+        glib::spawn_future_local(async move {
+            let result = runtime
+                .spawn(async { IpRanges::download().await })
+                .await
+                .unwrap()
+                .and_then(|json| IpRanges::store(&json));
+
+            button.set_sensitive(true);
+            match result {
+                Ok(_) => status_clone.set_text(&describe_ip_ranges_cache()),
+                Err(e) => show_error_dialog(
+                    &parent_for_error,
+                    "IP ranges",
+                    &format!("Failed to download IP ranges:\n{:#}", e),
+                ),
+            }
         });
     });
 
@@ -1707,20 +2993,20 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
 
             let game_path_text = game_path_entry.text().to_string();
             if !game_path_text.trim().is_empty()
-                && !is_valid_game_folder(std::path::Path::new(game_path_text.trim()))
+                && !game_install::is_valid_game_folder(std::path::Path::new(game_path_text.trim()))
             {
                 show_error_dialog(
                     &parent_clone_for_save,
                     "Invalid game folder",
-                    "Please select the folder named \"Dead by Daylight\".",
+                    INVALID_GAME_FOLDER,
                 );
                 return;
             }
 
-            settings.apply_mode = match mode_combo.active() {
-                Some(1) => ApplyMode::UniversalRedirect,
-                _ => ApplyMode::Gatekeep,
-            };
+            settings.apply_mode = mode_combo.active()
+                .and_then(|index| app_state_clone.backends.all().get(index as usize))
+                .map(|backend| backend.mode())
+                .unwrap_or(ApplyMode::Gatekeep);
 
             settings.block_mode = if rb_both.is_active() {
                 BlockMode::Both
@@ -1731,7 +3017,23 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
             };
 
             settings.merge_unstable = merge_check.is_active();
+            settings.merge_with_stable = merge_with_stable_check.is_active();
+            settings.refresh_redirect_ips = refresh_check.is_active();
+            settings.dns_proxy_redirect = proxy_redirect_check.is_active();
+            settings.game_session = session_check.is_active();
+            // The first entry is the ticked servers, the profiles follow in order
+            settings.game_session_profile = session_combo.active()
+                .filter(|index| *index > 0)
+                .and_then(|index| settings.profiles.get(index as usize - 1))
+                .map(|profile| profile.name.clone());
+            settings.game_launcher = if game_path_text.trim().is_empty() {
+                game_install::Launcher::Other
+            } else {
+                game_install::launcher_for(std::path::Path::new(game_path_text.trim()))
+            };
             settings.game_path = game_path_text;
+            let hosts_path_text = hosts_path_entry.text().trim().to_string();
+            settings.hosts_path = if hosts_path_text.is_empty() { None } else { Some(hosts_path_text) };
 
             let _ = settings.save();
 
@@ -1751,15 +3053,29 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
             settings.apply_mode = ApplyMode::Gatekeep;
             settings.block_mode = BlockMode::Both;
             settings.merge_unstable = true;
+            settings.merge_with_stable = false;
+            settings.refresh_redirect_ips = false;
+            settings.dns_proxy_redirect = false;
+            settings.game_session = false;
+            settings.game_session_profile = None;
             settings.game_path.clear();
+            settings.game_launcher = game_install::Launcher::Other;
+            settings.hosts_path = None;
 
             let _ = settings.save();
 
             // Update UI controls to reflect defaults
             game_path_entry.set_text("");
+            game_launcher_label.set_text("");
+            hosts_path_entry.set_text("");
             mode_combo.set_active(Some(0));
             rb_both.set_active(true);
             merge_check.set_active(true);
+            merge_with_stable_check.set_active(false);
+            refresh_check.set_active(false);
+            proxy_redirect_check.set_active(false);
+            session_check.set_active(false);
+            session_combo.set_active(Some(0));
 
             // Refresh the warning symbols in the list view
             refresh_warning_symbols(
@@ -1778,39 +3094,53 @@ fn show_settings_dialog(app_state: &Rc<AppState>, parent: &ApplicationWindow) {
     dialog.show();
 }
 
+fn describe_ip_ranges_cache() -> String {
+    match IpRanges::load_cached() {
+        Ok(Some(ranges)) => format!(
+            "Published {} (syncToken {}).",
+            ranges.create_date, ranges.sync_token
+        ),
+        Ok(None) => "Not downloaded yet.".to_string(),
+        Err(e) => format!("The cached file is invalid: {:#}", e),
+    }
+}
+
 fn get_saved_game_path(
     app_state: &Rc<AppState>,
     window: &ApplicationWindow,
 ) -> Option<std::path::PathBuf> {
-    let settings = app_state.settings.lock().unwrap();
+    let mut settings = app_state.settings.lock().unwrap();
+
+    // With a single install there is nothing to ask, it is remembered for next time
+    if settings.game_path.trim().is_empty() {
+        if let [install] = game_install::find_game_installs().as_slice() {
+            settings.game_path = install.path.to_string_lossy().to_string();
+            settings.game_launcher = install.launcher;
+            let _ = settings.save();
+        }
+    }
+
     let game_path = settings.game_path.trim();
     if game_path.is_empty() {
         show_info_dialog(
             window,
             "Game folder required",
-            "Please set the game folder in Options → Program settings.\n\nTip: In Steam, right-click Dead by Daylight → Manage → Browse local files. The folder that opens is the one you should select.",
+            "Please set the game folder in Options → Program settings. \"Detect\" lists the installs found in Steam, Heroic and Lutris.\n\nTip: In Steam, right-click Dead by Daylight → Manage → Browse local files. The folder that opens is the one you should select.",
         );
         return None;
     }
     let path = std::path::PathBuf::from(game_path);
-    if !is_valid_game_folder(&path) {
+    if !game_install::is_valid_game_folder(&path) {
         show_error_dialog(
             window,
             "Invalid game folder",
-            "Please select the folder named 'Dead by Daylight'.",
+            INVALID_GAME_FOLDER,
         );
         return None;
     }
     Some(path)
 }
 
-fn is_valid_game_folder(path: &std::path::Path) -> bool {
-    path.file_name()
-        .and_then(|name| name.to_str())
-        .map(|name| name == "Dead by Daylight")
-        .unwrap_or(false)
-}
-
 fn show_info_dialog(parent: &ApplicationWindow, title: &str, message: &str) {
     let dialog = MessageDialog::new(
         Some(parent),
@@ -1823,6 +3153,18 @@ fn show_info_dialog(parent: &ApplicationWindow, title: &str, message: &str) {
     dialog.run_async(|dialog, _| dialog.close());
 }
 
+fn show_warning_dialog(parent: &ApplicationWindow, title: &str, message: &str) {
+    let dialog = MessageDialog::new(
+        Some(parent),
+        gtk4::DialogFlags::MODAL,
+        MessageType::Warning,
+        ButtonsType::Ok,
+        title,
+    );
+    dialog.set_secondary_text(Some(message));
+    dialog.run_async(|dialog, _| dialog.close());
+}
+
 fn show_error_dialog(parent: &ApplicationWindow, title: &str, message: &str) {
     let dialog = MessageDialog::new(
         Some(parent),
@@ -1891,3 +3233,151 @@ fn start_ping_timer(app_state: Rc<AppState>) {
         glib::ControlFlow::Continue
     });
 }
+
+fn start_hosts_watcher(app_state: Rc<AppState>) {
+    let watcher = match hosts_watch::HostsWatcher::start(app_state.hosts_manager.path()) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Not watching {}: {:#}", app_state.hosts_manager.path(), e);
+            return;
+        }
+    };
+
+    glib::timeout_add_local(std::time::Duration::from_millis(500), move || {
+        if watcher.changed() {
+            refresh_hosts_status(&app_state);
+        }
+        glib::ControlFlow::Continue
+    });
+}
+
+// Re-reads the managed section and conflicts, updating the status line and, when the file
+// no longer matches the current selection, the checkboxes
+fn refresh_hosts_status(app_state: &AppState) {
+    let all_regions = get_all_regions_map(&app_state.regions, &app_state.blocked_regions);
+    let conflicts = app_state.hosts_manager.detect_conflicting_entries(&all_regions)
+        .map(|conflicts| conflicts.len())
+        .unwrap_or(0);
+    let (merge_unstable, merge_with_stable, redirect_region) = {
+        let settings = app_state.settings.lock().unwrap();
+        let options = current_apply_options(app_state, &settings);
+        (options.merge_unstable, settings.merge_with_stable, settings.redirect_region.clone())
+    };
+
+    let mut status = match app_state.hosts_manager.applied_state(&app_state.regions) {
+        Ok(hosts::AppliedState::Gatekeep { allowed }) => {
+            let selected = app_state.selected_regions.borrow().clone();
+            if get_allowed_regions(&app_state.regions, &selected, merge_unstable, merge_with_stable) != allowed {
+                set_checked_regions(app_state, &allowed);
+            }
+            format!("Hosts file: Gatekeep active, {} servers allowed.", allowed.len())
+        }
+        Ok(hosts::AppliedState::UniversalRedirect) => match redirect_region {
+            Some(region) if app_state.regions.contains_key(&region) => {
+                set_checked_regions(app_state, &HashSet::from([region.clone()]));
+                format!("Hosts file: Universal Redirect to {}.", region)
+            }
+            _ => "Hosts file: Universal Redirect active.".to_string(),
+        },
+        Ok(hosts::AppliedState::NotApplied) => "Hosts file: no Make Your Choice entries.".to_string(),
+        Err(e) => format!("Hosts file: could not be read ({}).", e),
+    };
+
+    if conflicts > 0 {
+        status.push_str(&format!(" {} conflicting entries outside the managed section.", conflicts));
+    }
+    app_state.status_label.set_text(&status);
+}
+
+fn set_checked_regions(app_state: &AppState, regions: &HashSet<String>) {
+    let list_store = &app_state.list_store;
+    let mut selected = app_state.selected_regions.borrow_mut();
+    selected.clear();
+
+    if let Some(iter) = list_store.iter_first() {
+        loop {
+            if !list_store.get::<bool>(&iter, 4) {
+                let name = list_store.get::<String>(&iter, 0).replace(" ⚠︎", "");
+                let checked = regions.contains(&name);
+                list_store.set(&iter, &[(3, &checked)]);
+                if checked {
+                    selected.insert(name);
+                }
+            }
+            if !list_store.iter_next(&iter) {
+                break;
+            }
+        }
+    }
+}
+
+fn start_redirect_refresh_timer(app_state: Rc<AppState>, window: &ApplicationWindow) {
+    let window = window.clone();
+    glib::timeout_add_seconds_local(refresh::REFRESH_INTERVAL_SECS, move || {
+        let region = {
+            let settings = app_state.settings.lock().unwrap();
+            // Only set while a Universal Redirect applied from the app or a profile is in place
+            match (settings.refresh_redirect_ips, &settings.redirect_region) {
+                (true, Some(region)) => region.clone(),
+                _ => return glib::ControlFlow::Continue,
+            }
+        };
+
+        let region_info = match app_state.regions.get(&region) {
+            Some(info) => info.clone(),
+            None => return glib::ControlFlow::Continue,
+        };
+
+        let pinned = match app_state.hosts_manager.pinned_redirect_ips(&region_info) {
+            Ok(Some(pinned)) => pinned,
+            _ => return glib::ControlFlow::Continue,
+        };
+
+        let app_state = app_state.clone();
+        let window = window.clone();
+        let runtime = app_state.tokio_runtime.clone();
+
+        glib::spawn_future_local(async move {
+            // DNS lookups block, keep them off the main thread
+            let fresh = runtime
+                .spawn_blocking(move || refresh::resolve_region(&region_info))
+                .await
+                .unwrap();
+
+            // Failed lookups are retried on the next tick, the old pins keep working until then
+            let current = match fresh {
+                Ok(fresh) => match refresh::stale_pins(&pinned, &fresh) {
+                    Some(current) => current,
+                    None => return,
+                },
+                Err(_) => return,
+            };
+
+            match app_state.hosts_manager.write_universal_redirect(
+                &app_state.regions,
+                &app_state.blocked_regions,
+                &current,
+            ) {
+                Ok(_) => {
+                    show_info_dialog(
+                        &window,
+                        "Universal Redirect",
+                        &format!(
+                            "The IP addresses of {} changed and the hosts file was updated.\n\nService: {} → {}\nPing: {} → {}",
+                            region, pinned.service, current.service, pinned.ping, current.ping
+                        ),
+                    );
+                }
+                Err(e) => {
+                    show_error_dialog(
+                        &window,
+                        "Universal Redirect",
+                        &format!("Failed to refresh the redirect IPs:\n{}", e),
+                    );
+                }
+            }
+        });
+
+        glib::ControlFlow::Continue
+    });
+}
@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use crate::dns;
+use crate::hosts::{HostsManager, RedirectIps, redirect_hosts};
+use crate::region::RegionInfo;
+
+// How often the app re-checks the pinned Universal Redirect IPs while it is open
+pub const REFRESH_INTERVAL_SECS: u32 = 6 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    // The hosts file doesn't hold a Universal Redirect section (anymore)
+    NotApplied,
+    Unchanged,
+    Updated { previous: RedirectIps, current: RedirectIps },
+}
+
+// Fresh address sets for a region's service and ping endpoints
+#[derive(Debug, Clone)]
+pub struct ResolvedAddresses {
+    pub service: Vec<String>,
+    pub ping: Vec<String>,
+}
+
+impl ResolvedAddresses {
+    // GameLift answers round-robin, so a pinned IP only counts as stale once it has
+    // dropped out of the address set entirely
+    pub fn contains(&self, ips: &RedirectIps) -> bool {
+        self.service.contains(&ips.service) && self.ping.contains(&ips.ping)
+    }
+
+    pub fn pick(&self) -> Option<RedirectIps> {
+        Some(RedirectIps {
+            service: self.service.first()?.clone(),
+            ping: self.ping.first()?.clone(),
+        })
+    }
+}
+
+// Blocking: talks to the upstream nameservers directly, /etc/hosts would only echo our own pins
+pub fn resolve_region(region_info: &RegionInfo) -> Result<ResolvedAddresses> {
+    let (service_host, ping_host) = redirect_hosts(region_info);
+    let lookup = |host: &str| -> Result<Vec<String>> {
+        let addrs = dns::resolve_upstream(host)
+            .with_context(|| format!("Failed to resolve {}", host))?;
+        Ok(addrs.iter().map(|a| a.to_string()).collect())
+    };
+
+    Ok(ResolvedAddresses {
+        service: lookup(service_host)?,
+        ping: lookup(ping_host)?,
+    })
+}
+
+// Decides whether the section has to be rewritten; None means the pinned IPs are still current.
+pub fn stale_pins(pinned: &RedirectIps, fresh: &ResolvedAddresses) -> Option<RedirectIps> {
+    if fresh.contains(pinned) {
+        None
+    } else {
+        fresh.pick()
+    }
+}
+
+pub fn refresh_universal_redirect(
+    hosts_manager: &HostsManager,
+    regions: &HashMap<String, RegionInfo>,
+    blocked_regions: &HashMap<String, RegionInfo>,
+    selected_region: &str,
+) -> Result<RefreshOutcome> {
+    let region_info = regions.get(selected_region)
+        .with_context(|| format!("Unknown region: {}", selected_region))?;
+
+    let pinned = match hosts_manager.pinned_redirect_ips(region_info)? {
+        Some(pinned) => pinned,
+        None => return Ok(RefreshOutcome::NotApplied),
+    };
+
+    let fresh = resolve_region(region_info)?;
+    match stale_pins(&pinned, &fresh) {
+        Some(current) => {
+            hosts_manager.write_universal_redirect(regions, blocked_regions, &current)?;
+            Ok(RefreshOutcome::Updated { previous: pinned, current })
+        }
+        None => Ok(RefreshOutcome::Unchanged),
+    }
+}
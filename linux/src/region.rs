@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegionInfo {
@@ -11,6 +11,9 @@ pub struct RegionInfo {
 pub enum ApplyMode {
     Gatekeep,
     UniversalRedirect,
+    Firewall,
+    DnsProxy,
+    SystemdResolved,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -292,6 +295,13 @@ pub fn get_blocked_regions() -> HashMap<String, RegionInfo> {
     regions
 }
 
+// Selectable and always-blocked regions together
+pub fn get_all_regions() -> HashMap<String, RegionInfo> {
+    let mut regions = get_selectable_regions();
+    regions.extend(get_blocked_regions());
+    regions
+}
+
 pub fn get_group_name(region: &str) -> &'static str {
     if region.starts_with("Europe") {
         "Europe"
@@ -305,3 +315,163 @@ pub fn get_group_name(region: &str) -> &'static str {
         "Asia"
     }
 }
+
+// Stable region allowed next to an unstable one when merging, the closest one by latency
+const PREFERRED_FALLBACKS: [(&str, &str); 3] = [
+    ("Europe (London)", "Europe (Ireland)"),
+    ("US East (Ohio)", "US East (N. Virginia)"),
+    ("Canada (Central)", "US East (N. Virginia)"),
+];
+
+// The preferred fallback for `region`, or if that isn't a stable region of the catalog, the
+// first stable region of the same group by name
+pub fn get_fallback_region<'a>(regions: &'a HashMap<String, RegionInfo>, region: &str) -> Option<&'a str> {
+    let is_stable = |name: &str| regions.get(name).map(|info| info.stable).unwrap_or(false);
+
+    let preferred = PREFERRED_FALLBACKS.iter()
+        .find(|(unstable, _)| *unstable == region)
+        .map(|(_, fallback)| *fallback)
+        .filter(|fallback| is_stable(fallback));
+    if let Some(fallback) = preferred {
+        return regions.get_key_value(fallback).map(|(name, _)| name.as_str());
+    }
+
+    let group = get_group_name(region);
+    regions.iter()
+        .filter(|(name, info)| info.stable && get_group_name(name) == group)
+        .map(|(name, _)| name.as_str())
+        .min()
+}
+
+// Selected unstable regions paired with the fallback merging adds for them, sorted. By default
+// only selections without any stable region get fallbacks; `merge_with_stable` adds them anyway.
+pub fn get_fallbacks(
+    regions: &HashMap<String, RegionInfo>,
+    selected: &HashSet<String>,
+    merge_unstable: bool,
+    merge_with_stable: bool,
+) -> Vec<(String, String)> {
+    // Check if any stable servers are selected
+    let any_stable_selected = selected.iter()
+        .any(|r| regions.get(r).map(|info| info.stable).unwrap_or(false));
+    if !merge_unstable || (any_stable_selected && !merge_with_stable) {
+        return Vec::new();
+    }
+
+    let mut fallbacks: Vec<(String, String)> = selected.iter()
+        .filter(|region| regions.get(*region).map(|info| !info.stable).unwrap_or(false))
+        .filter_map(|region| {
+            let fallback = get_fallback_region(regions, region)?;
+            Some((region.clone(), fallback.to_string()))
+        })
+        // Already allowed, nothing is added
+        .filter(|(_, fallback)| !selected.contains(fallback))
+        .collect();
+    fallbacks.sort();
+    fallbacks
+}
+
+// The selection plus the fallbacks merging adds for unstable regions
+pub fn get_allowed_regions(
+    regions: &HashMap<String, RegionInfo>,
+    selected: &HashSet<String>,
+    merge_unstable: bool,
+    merge_with_stable: bool,
+) -> HashSet<String> {
+    let mut allowed_set = selected.clone();
+    for (_, fallback) in get_fallbacks(regions, selected, merge_unstable, merge_with_stable) {
+        allowed_set.insert(fallback);
+    }
+    allowed_set
+}
+
+// AWS region code of a region, taken from its endpoints (gamelift.eu-west-1.amazonaws.com -> eu-west-1)
+pub fn get_region_code(region_info: &RegionInfo) -> Option<&str> {
+    region_info.hosts.iter()
+        .find_map(|host| host.split('.').nth(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn pair(region: &str, fallback: &str) -> (String, String) {
+        (region.to_string(), fallback.to_string())
+    }
+
+    #[test]
+    fn preferred_fallbacks_are_stable_regions_of_the_catalog() {
+        let regions = get_selectable_regions();
+        for (unstable, fallback) in PREFERRED_FALLBACKS {
+            assert!(!regions[unstable].stable, "{} is stable", unstable);
+            assert!(regions[fallback].stable, "{} is not stable", fallback);
+            assert_eq!(get_fallback_region(&regions, unstable), Some(fallback));
+        }
+    }
+
+    #[test]
+    fn every_unstable_region_has_a_fallback() {
+        let regions = get_selectable_regions();
+        for (name, info) in &regions {
+            if !info.stable {
+                assert!(get_fallback_region(&regions, name).is_some(), "no fallback for {}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn fallbacks_for_an_unstable_only_selection() {
+        let regions = get_selectable_regions();
+        let selected = selection(&["Europe (London)", "US East (Ohio)", "Canada (Central)"]);
+
+        assert_eq!(
+            get_fallbacks(&regions, &selected, true, false),
+            vec![
+                pair("Canada (Central)", "US East (N. Virginia)"),
+                pair("Europe (London)", "Europe (Ireland)"),
+                pair("US East (Ohio)", "US East (N. Virginia)"),
+            ]
+        );
+        assert_eq!(
+            get_allowed_regions(&regions, &selected, true, false),
+            selection(&[
+                "Europe (London)",
+                "US East (Ohio)",
+                "Canada (Central)",
+                "Europe (Ireland)",
+                "US East (N. Virginia)",
+            ])
+        );
+        assert!(get_fallbacks(&regions, &selected, false, true).is_empty());
+    }
+
+    #[test]
+    fn stable_selection_only_gets_fallbacks_when_configured() {
+        let regions = get_selectable_regions();
+        let selected = selection(&["Europe (London)", "Asia Pacific (Tokyo)"]);
+
+        assert!(get_fallbacks(&regions, &selected, true, false).is_empty());
+        assert_eq!(
+            get_fallbacks(&regions, &selected, true, true),
+            vec![pair("Europe (London)", "Europe (Ireland)")]
+        );
+    }
+
+    #[test]
+    fn selected_fallback_is_not_reported_as_added() {
+        let regions = get_selectable_regions();
+        let selected = selection(&["Europe (London)", "Europe (Ireland)"]);
+        assert!(get_fallbacks(&regions, &selected, true, true).is_empty());
+    }
+
+    #[test]
+    fn group_fallback_is_picked_by_name_when_the_preferred_one_is_missing() {
+        let mut regions = get_selectable_regions();
+        regions.remove("Europe (Ireland)");
+        assert_eq!(get_fallback_region(&regions, "Europe (London)"), Some("Europe (Frankfurt am Main)"));
+    }
+}
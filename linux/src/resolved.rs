@@ -0,0 +1,190 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::dns_proxy::{self, DnsProxyManager, ProxyRules};
+
+const DROP_IN_DIR: &str = "/etc/systemd/resolved.conf.d";
+pub const DROP_IN_PATH: &str = "/etc/systemd/resolved.conf.d/make-your-choice.conf";
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const RESOLVED_STUB: &str = "127.0.0.53";
+// Every GameLift endpoint lives under one of these
+const ROUTING_DOMAINS: [&str; 2] = ["amazonaws.com", "api.aws"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolvedState {
+    NotRunning,
+    // resolved runs, but /etc/resolv.conf bypasses its stub so programs never ask it
+    StubNotUsed,
+    Usable,
+}
+
+pub fn detect() -> ResolvedState {
+    let running = Command::new("systemctl")
+        .args(["is-active", "--quiet", "systemd-resolved.service"])
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !running {
+        return ResolvedState::NotRunning;
+    }
+
+    let resolv_conf = fs::read_to_string(RESOLV_CONF).unwrap_or_default();
+    let uses_stub = resolv_conf.lines().any(|line| {
+        let mut parts = line.split_whitespace();
+        parts.next() == Some("nameserver") && parts.next() == Some(RESOLVED_STUB)
+    });
+
+    if uses_stub {
+        ResolvedState::Usable
+    } else {
+        ResolvedState::StubNotUsed
+    }
+}
+
+// resolved.conf has no routing-only servers: besides the routing domains, resolved also sends
+// lookups no link claims to the global DNS= servers. Those reach the proxy too, which forwards
+// them unchanged to the upstream servers, so the proxy is a global resolver while this is applied.
+pub fn build_drop_in() -> String {
+    let domains: Vec<String> = ROUTING_DOMAINS.iter().map(|d| format!("~{}", d)).collect();
+    format!(
+        "# Managed by Make Your Choice (DbD Server Selector), removed on revert.\n\
+        # Routes GameLift lookups to the local DNS proxy. resolved may send other lookups to it\n\
+        # as well, the proxy forwards those unchanged to your normal DNS servers.\n\
+        [Resolve]\n\
+        DNS={}\n\
+        Domains={}\n",
+        dns_proxy::LISTEN_ADDR,
+        domains.join(" ")
+    )
+}
+
+// Expresses the selection through systemd-resolved: the DNS proxy answers GameLift names and
+// a resolved drop-in makes it the server for amazonaws.com and api.aws
+#[derive(Default)]
+pub struct ResolvedManager {
+    proxy: DnsProxyManager,
+}
+
+impl ResolvedManager {
+    pub fn new() -> Self {
+        Self { proxy: DnsProxyManager::new() }
+    }
+
+    pub fn is_active(&self) -> bool {
+        Path::new(DROP_IN_PATH).exists()
+    }
+
+    pub fn apply(&self, rules: &ProxyRules) -> Result<()> {
+        match detect() {
+            ResolvedState::NotRunning => bail!(
+                "systemd-resolved is not running on this system.\n\nPlease use the DNS proxy method and point your resolver at it manually (see the README)."
+            ),
+            ResolvedState::StubNotUsed => bail!(
+                "systemd-resolved is running, but {} doesn't point to its stub resolver ({}), so programs never ask it.\n\nPlease use the DNS proxy method and point your resolver at it manually (see the README).",
+                RESOLV_CONF, RESOLVED_STUB
+            ),
+            ResolvedState::Usable => {}
+        }
+
+        // The proxy has to be listening before resolved starts sending lookups to it
+        self.proxy.apply(rules)?;
+
+        // The drop-in goes to the root side over stdin, a file in /tmp could be swapped for a
+        // symlink before root copies it
+        let command = format!(
+            "mkdir -p {} && install -m 644 /dev/stdin {} && systemctl restart systemd-resolved",
+            DROP_IN_DIR, DROP_IN_PATH
+        );
+        run_privileged_with_input(&command, &build_drop_in())
+            .context("Failed to configure systemd-resolved. Operation was cancelled or permission was denied.")
+    }
+
+    // Only removes the drop-in, the proxy is torn down by whoever owns it
+    pub fn revert(&self) -> Result<()> {
+        if !self.is_active() {
+            return Ok(());
+        }
+
+        let command = format!("rm -f {} && systemctl restart systemd-resolved", DROP_IN_PATH);
+        run_privileged(&command)
+            .context("Failed to remove the systemd-resolved configuration. Operation was cancelled or permission was denied.")
+    }
+
+    pub fn describe_status(&self) -> String {
+        let state = match detect() {
+            ResolvedState::NotRunning => "not running",
+            ResolvedState::StubNotUsed => "running, but not used by /etc/resolv.conf",
+            ResolvedState::Usable => "running",
+        };
+
+        if self.is_active() {
+            format!(
+                "systemd-resolved is {} and sends {} lookups to the DNS proxy on {}.",
+                state,
+                ROUTING_DOMAINS.join(" and "),
+                dns_proxy::LISTEN_ADDR
+            )
+        } else {
+            format!("systemd-resolved is {}, Make Your Choice hasn't configured it.", state)
+        }
+    }
+}
+
+fn run_privileged(command: &str) -> Result<()> {
+    let status = Command::new("pkexec")
+        .arg("sh")
+        .arg("-c")
+        .arg(command)
+        .status()
+        .context("Failed to execute pkexec")?;
+
+    if !status.success() {
+        bail!("pkexec exited with {}", status);
+    }
+    Ok(())
+}
+
+fn run_privileged_with_input(command: &str, input: &str) -> Result<()> {
+    let mut child = Command::new("pkexec")
+        .arg("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to execute pkexec")?;
+
+    // Dropping stdin after the write closes it, so the command sees the end of the input. When
+    // pkexec was cancelled the write fails as well, its exit status is the better error.
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(input.as_bytes()),
+        None => Ok(()),
+    };
+
+    let status = child.wait().context("Failed to wait for pkexec")?;
+    if !status.success() {
+        bail!("pkexec exited with {}", status);
+    }
+    written.context("Failed to pass the configuration to pkexec")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_in_routes_only_gamelift_domains_to_the_proxy() {
+        let drop_in = build_drop_in();
+        let settings: Vec<&str> = drop_in.lines().filter(|line| !line.starts_with('#')).collect();
+
+        assert_eq!(
+            settings,
+            ["[Resolve]", "DNS=127.0.0.1:5354", "Domains=~amazonaws.com ~api.aws"]
+        );
+        // "~." would claim every lookup, plain domains would be appended to short names
+        assert!(!settings.iter().any(|line| line.contains("~.") || line.starts_with("DNSStubListener")));
+    }
+}
@@ -0,0 +1,343 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::backend::Backends;
+use crate::settings::UserSettings;
+use crate::systemd::{systemctl, user_unit_dir};
+
+// One pair of .service/.timer units per schedule, numbered in the order of the settings
+const UNIT_PREFIX: &str = "make-your-choice-schedule-";
+
+pub const EVERY_DAY: &[Weekday] = &[];
+pub const WEEKDAYS: &[Weekday] = &[Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+pub const WEEKENDS: &[Weekday] = &[Weekday::Sat, Weekday::Sun];
+
+// Switches to a saved profile at a time of day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub profile: String,
+    // "HH:MM", local time
+    pub time: String,
+    // Empty means every day
+    pub days: Vec<Weekday>,
+}
+
+impl Schedule {
+    pub fn new(profile: &str, time: &str, days: &[Weekday]) -> Result<Self> {
+        let time = parse_time(time)?;
+        Ok(Self {
+            profile: profile.to_string(),
+            time: time.format("%H:%M").to_string(),
+            days: days.to_vec(),
+        })
+    }
+
+    pub fn runs_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    pub fn describe_days(&self) -> String {
+        let mut days = self.days.clone();
+        days.sort_by_key(|day| day.num_days_from_monday());
+        days.dedup();
+
+        if days.is_empty() || days.len() == 7 {
+            "every day".to_string()
+        } else if days == WEEKDAYS {
+            "weekdays".to_string()
+        } else if days == WEEKENDS {
+            "weekends".to_string()
+        } else {
+            days.iter().map(|day| day.to_string()).collect::<Vec<_>>().join(", ")
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} {}: {}", self.time, self.describe_days(), self.profile)
+    }
+
+    // The same moments as an OnCalendar= expression for a systemd timer
+    pub fn on_calendar(&self) -> String {
+        if self.days.is_empty() {
+            format!("*-*-* {}:00", self.time)
+        } else {
+            let days: Vec<String> = self.days.iter().map(|day| day.to_string()).collect();
+            format!("{} *-*-* {}:00", days.join(","), self.time)
+        }
+    }
+
+    // The first moment strictly after `after` this schedule fires, None if the time is invalid
+    pub fn next_run(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let time = parse_time(&self.time).ok()?;
+        (0..=7)
+            .map(|offset| after.date() + Duration::days(offset))
+            .filter(|date| self.runs_on(date.weekday()))
+            .map(|date| date.and_time(time))
+            .find(|run| *run > after)
+    }
+
+    // The latest moment in (since, now] this schedule fired
+    fn last_run(&self, since: NaiveDateTime, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let time = parse_time(&self.time).ok()?;
+        // Only the last week matters, every schedule repeats at least weekly
+        (0..=7)
+            .map(|offset| now.date() - Duration::days(offset))
+            .filter(|date| self.runs_on(date.weekday()))
+            .map(|date| date.and_time(time))
+            .find(|run| *run > since && *run <= now)
+    }
+}
+
+pub fn parse_time(text: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .with_context(|| format!("\"{}\" is not a time of day like 07:30 or 19:00", text.trim()))
+}
+
+// The schedule that fired last between the two checks. When several fired since the last
+// check (e.g. after the computer was suspended), only the most recent one is still relevant.
+pub fn due(schedules: &[Schedule], since: NaiveDateTime, now: NaiveDateTime) -> Option<&Schedule> {
+    schedules
+        .iter()
+        .filter_map(|schedule| schedule.last_run(since, now).map(|run| (run, schedule)))
+        .max_by_key(|(run, _)| *run)
+        .map(|(_, schedule)| schedule)
+}
+
+// systemd expands % specifiers in most unit settings, including Description=
+fn escape_specifiers(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+// Quotes a word for ExecStart=
+fn quote_exec_arg(arg: &str) -> String {
+    format!("\"{}\"", escape_specifiers(&arg.replace('\\', "\\\\").replace('"', "\\\"")))
+}
+
+// Without `hosts_path` the timer applies to the hosts file from the settings when it fires
+fn service_unit(schedule: &Schedule, exe: &Path, hosts_path: Option<&str>) -> String {
+    let hosts_option = hosts_path
+        .map(|path| format!(" --hosts-file {}", quote_exec_arg(path)))
+        .unwrap_or_default();
+    format!(
+        "[Unit]\nDescription=Make Your Choice: switch to profile {}\n\n[Service]\nType=oneshot\nExecStart={}{} apply --profile {}\n",
+        escape_specifiers(&schedule.profile),
+        quote_exec_arg(&exe.to_string_lossy()),
+        hosts_option,
+        quote_exec_arg(&schedule.profile)
+    )
+}
+
+fn timer_unit(schedule: &Schedule) -> String {
+    format!(
+        "[Unit]\nDescription=Make Your Choice: {}\n\n[Timer]\nOnCalendar={}\n\n[Install]\nWantedBy=timers.target\n",
+        escape_specifiers(&schedule.describe()),
+        schedule.on_calendar()
+    )
+}
+
+// Names of the schedule units currently in the user unit directory
+fn installed_units() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(user_unit_dir()) else {
+        return Vec::new();
+    };
+    let mut units: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.starts_with(UNIT_PREFIX))
+        .collect();
+    units.sort();
+    units
+}
+
+pub fn timers_installed() -> bool {
+    installed_units().iter().any(|name| name.ends_with(".timer"))
+}
+
+// The timers run `apply` from a systemd user service. There is no polkit agent to ask for a
+// password there, so pkexec fails and only methods that work without it can be scheduled.
+// Applying also turns off the other active methods, which may need a password as well.
+pub fn check_unattended(schedules: &[Schedule], settings: &UserSettings, backends: &Backends) -> Result<()> {
+    let mut blocked: Vec<String> = Vec::new();
+    for schedule in schedules {
+        let Some(profile) = settings.profile(&schedule.profile) else {
+            continue;
+        };
+        let prompts = backends.password_prompts(profile.apply_mode);
+        if prompts.is_empty() {
+            continue;
+        }
+
+        let reasons: Vec<String> = prompts
+            .iter()
+            .map(|backend| if backend.mode() == profile.apply_mode {
+                backend.label().to_string()
+            } else {
+                format!("turning off {}", backend.label())
+            })
+            .collect();
+        let entry = format!("{} ({})", profile.name, reasons.join(", "));
+        if !blocked.contains(&entry) {
+            blocked.push(entry);
+        }
+    }
+
+    if !blocked.is_empty() {
+        bail!(
+            "Timers can't ask for your password, so they can't apply these profiles: {}.\n\nUse the DNS proxy method for scheduled profiles and revert methods that need a password first, make the hosts file writable for your user, or let the app switch while it is open.",
+            blocked.join(", ")
+        );
+    }
+    Ok(())
+}
+
+// Replaces all schedule timers with one per schedule. They call the command line interface,
+// so the profile is switched even while the app is closed.
+pub fn install_timers(schedules: &[Schedule], exe: &Path, hosts_path: Option<&str>) -> Result<()> {
+    if schedules.is_empty() {
+        bail!("There are no schedules to install timers for.");
+    }
+    remove_timers()?;
+
+    let dir = user_unit_dir();
+    fs::create_dir_all(&dir).context("Failed to create the systemd user unit directory")?;
+
+    let mut timers = Vec::new();
+    for (index, schedule) in schedules.iter().enumerate() {
+        let name = format!("{}{}", UNIT_PREFIX, index + 1);
+        fs::write(dir.join(format!("{}.service", name)), service_unit(schedule, exe, hosts_path))
+            .context("Failed to write the schedule service unit")?;
+        fs::write(dir.join(format!("{}.timer", name)), timer_unit(schedule))
+            .context("Failed to write the schedule timer unit")?;
+        timers.push(format!("{}.timer", name));
+    }
+
+    systemctl(&["daemon-reload"])?;
+    let mut args = vec!["enable", "--now"];
+    args.extend(timers.iter().map(|timer| timer.as_str()));
+    systemctl(&args)
+}
+
+pub fn remove_timers() -> Result<()> {
+    let units = installed_units();
+    if units.is_empty() {
+        return Ok(());
+    }
+
+    let timers: Vec<&str> = units.iter().map(|unit| unit.as_str()).filter(|unit| unit.ends_with(".timer")).collect();
+    if !timers.is_empty() {
+        let mut args = vec!["disable", "--now"];
+        args.extend(timers);
+        systemctl(&args)?;
+    }
+
+    let dir = user_unit_dir();
+    for unit in &units {
+        fs::remove_file(dir.join(unit)).with_context(|| format!("Failed to remove {}", unit))?;
+    }
+    systemctl(&["daemon-reload"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    // 2026-10-19 is a Monday
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_time(parse_time(time).unwrap())
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        assert!(Schedule::new("EU", "25:00", EVERY_DAY).is_err());
+        assert!(Schedule::new("EU", "evening", EVERY_DAY).is_err());
+        assert_eq!(Schedule::new("EU", " 7:05 ", EVERY_DAY).unwrap().time, "07:05");
+    }
+
+    #[test]
+    fn describes_day_presets() {
+        let schedule = |days: &[Weekday]| Schedule::new("EU", "19:00", days).unwrap();
+        assert_eq!(schedule(EVERY_DAY).describe(), "19:00 every day: EU");
+        assert_eq!(schedule(WEEKDAYS).describe_days(), "weekdays");
+        assert_eq!(schedule(&[Weekday::Sun, Weekday::Sat]).describe_days(), "weekends");
+        assert_eq!(schedule(&[Weekday::Fri, Weekday::Mon]).describe_days(), "Mon, Fri");
+    }
+
+    #[test]
+    fn on_calendar_lists_days() {
+        assert_eq!(Schedule::new("EU", "07:30", EVERY_DAY).unwrap().on_calendar(), "*-*-* 07:30:00");
+        assert_eq!(
+            Schedule::new("EU", "07:30", WEEKENDS).unwrap().on_calendar(),
+            "Sat,Sun *-*-* 07:30:00"
+        );
+    }
+
+    #[test]
+    fn next_run_skips_other_days() {
+        let weekends = Schedule::new("EU", "10:00", WEEKENDS).unwrap();
+        assert_eq!(weekends.next_run(at(19, "09:00")), Some(at(24, "10:00")));
+        let daily = Schedule::new("EU", "10:00", EVERY_DAY).unwrap();
+        assert_eq!(daily.next_run(at(19, "09:00")), Some(at(19, "10:00")));
+        assert_eq!(daily.next_run(at(19, "10:00")), Some(at(20, "10:00")));
+    }
+
+    #[test]
+    fn due_picks_the_latest_schedule_since_the_last_check() {
+        let schedules = vec![
+            Schedule::new("Morning", "08:00", EVERY_DAY).unwrap(),
+            Schedule::new("Evening", "18:00", WEEKDAYS).unwrap(),
+        ];
+
+        assert_eq!(due(&schedules, at(19, "07:59"), at(19, "08:00")).unwrap().profile, "Morning");
+        assert!(due(&schedules, at(19, "08:00"), at(19, "08:01")).is_none());
+        // Suspended over both, only the later one counts
+        assert_eq!(due(&schedules, at(19, "07:00"), at(19, "20:00")).unwrap().profile, "Evening");
+        // Saturday evening is not a weekday
+        assert_eq!(due(&schedules, at(23, "23:00"), at(24, "20:00")).unwrap().profile, "Morning");
+    }
+
+    #[test]
+    fn exec_args_are_quoted() {
+        let schedule = Schedule::new("100% \"EU\"", "19:00", EVERY_DAY).unwrap();
+        let unit = service_unit(&schedule, Path::new("/usr/bin/make-your-choice"), None);
+        assert!(unit.contains("ExecStart=\"/usr/bin/make-your-choice\" apply --profile \"100%% \\\"EU\\\"\"\n"));
+
+        let unit = service_unit(&schedule, Path::new("/usr/bin/make-your-choice"), Some("/srv/my hosts"));
+        assert!(unit.contains("ExecStart=\"/usr/bin/make-your-choice\" --hosts-file \"/srv/my hosts\" apply --profile"));
+    }
+
+    #[test]
+    fn only_schedules_profiles_that_apply_without_a_password() {
+        use crate::hosts::HostsManager;
+        use crate::region::{ApplyMode, BlockMode};
+        use crate::settings::Profile;
+
+        // A hosts file the user owns can be written without pkexec
+        let dir = std::env::temp_dir().join(format!("myc-unattended-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hosts = dir.join("hosts");
+        fs::write(&hosts, "127.0.0.1 localhost\n").unwrap();
+        let backends = Backends::new(HostsManager::with_path("https://discord.example".to_string(), hosts.to_string_lossy()));
+
+        let mut settings = UserSettings::default();
+        for (name, apply_mode) in [("EU", ApplyMode::Gatekeep), ("Proxy", ApplyMode::DnsProxy), ("Wall", ApplyMode::Firewall)] {
+            settings.save_profile(Profile {
+                name: name.to_string(),
+                selected: vec!["Europe (London)".to_string()],
+                apply_mode,
+                block_mode: BlockMode::Both,
+                merge_unstable: false,
+            });
+        }
+        let schedule = |profile: &str| Schedule::new(profile, "19:00", EVERY_DAY).unwrap();
+
+        assert!(check_unattended(&[schedule("EU"), schedule("Proxy"), schedule("Missing")], &settings, &backends).is_ok());
+        let error = check_unattended(&[schedule("Wall"), schedule("EU"), schedule("Wall")], &settings, &backends).unwrap_err();
+        assert!(error.to_string().contains("profiles: Wall (Firewall (nftables))."));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,17 +1,64 @@
+use crate::game_install::Launcher;
 use crate::region::{ApplyMode, BlockMode};
+use crate::schedule::Schedule;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UserSettings {
     pub apply_mode: ApplyMode,
     pub block_mode: BlockMode,
     pub merge_unstable: bool,
+    // Add the fallback for unstable servers even when stable servers are selected too
+    pub merge_with_stable: bool,
     pub last_launched_version: String,
     pub game_path: String,
+    // Store or launcher the game folder belongs to
+    pub game_launcher: Launcher,
     pub auto_update_check_paused_until: Option<String>,
+    pub redirect_region: Option<String>,
+    pub refresh_redirect_ips: bool,
+    pub dns_proxy_redirect: bool,
+    // Another hosts file than /etc/hosts, e.g. in a container rootfs or a Wine prefix
+    pub hosts_path: Option<String>,
+    pub profiles: Vec<Profile>,
+    // Servers last applied with the options above, game sessions without a profile use them
+    pub selection: Vec<String>,
+    pub schedules: Vec<Schedule>,
+    // Run the schedules with systemd user timers instead of only while the app is open
+    pub schedule_timers: bool,
+    // Apply when the game starts and revert when it exits
+    pub game_session: bool,
+    // Profile applied for a game session, None for the servers ticked in the app
+    pub game_session_profile: Option<String>,
+}
+
+// A named selection together with the options it is applied with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub selected: Vec<String>,
+    pub apply_mode: ApplyMode,
+    pub block_mode: BlockMode,
+    pub merge_unstable: bool,
+}
+
+impl Profile {
+    pub fn selection(&self) -> HashSet<String> {
+        self.selected.iter().cloned().collect()
+    }
+}
+
+// The options one apply runs with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApplyOptions {
+    pub apply_mode: ApplyMode,
+    pub block_mode: BlockMode,
+    pub merge_unstable: bool,
 }
 
 impl Default for UserSettings {
@@ -20,9 +67,21 @@ impl Default for UserSettings {
             apply_mode: ApplyMode::Gatekeep,
             block_mode: BlockMode::Both,
             merge_unstable: true,
+            merge_with_stable: false,
             last_launched_version: String::new(),
             game_path: String::new(),
+            game_launcher: Launcher::Other,
             auto_update_check_paused_until: None,
+            redirect_region: None,
+            refresh_redirect_ips: false,
+            dns_proxy_redirect: false,
+            hosts_path: None,
+            profiles: Vec::new(),
+            selection: Vec::new(),
+            schedules: Vec::new(),
+            schedule_timers: false,
+            game_session: false,
+            game_session_profile: None,
         }
     }
 }
@@ -38,6 +97,47 @@ impl UserSettings {
         Self::config_dir().join("config.yaml")
     }
 
+    // Profile names are matched ignoring case, like server names on the command line
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    // Replaces a profile with the same name, the list stays sorted by name
+    pub fn save_profile(&mut self, profile: Profile) {
+        self.remove_profile(&profile.name);
+        self.profiles.push(profile);
+        self.profiles.sort_by_key(|profile| profile.name.to_lowercase());
+    }
+
+    // The saved options, or the profile's laid over them. The settings themselves stay as they are.
+    pub fn apply_options(&self, profile: Option<&Profile>) -> ApplyOptions {
+        match profile {
+            Some(profile) => ApplyOptions {
+                apply_mode: profile.apply_mode,
+                block_mode: profile.block_mode,
+                merge_unstable: profile.merge_unstable,
+            },
+            None => ApplyOptions {
+                apply_mode: self.apply_mode,
+                block_mode: self.block_mode,
+                merge_unstable: self.merge_unstable,
+            },
+        }
+    }
+
+    // Remembers `selected` as the servers applied with the saved options
+    pub fn remember_selection(&mut self, selected: &HashSet<String>) {
+        let mut selection: Vec<String> = selected.iter().cloned().collect();
+        selection.sort();
+        self.selection = selection;
+    }
+
+    pub fn remove_profile(&mut self, name: &str) -> bool {
+        let count = self.profiles.len();
+        self.profiles.retain(|profile| !profile.name.eq_ignore_ascii_case(name.trim()));
+        self.profiles.len() != count
+    }
+
     pub fn load() -> Result<Self> {
         let path = Self::config_file();
         if !path.exists() {
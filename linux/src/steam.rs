@@ -0,0 +1,297 @@
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::game_install::{GameInstall, Launcher, is_valid_game_folder};
+
+// Dead by Daylight's Steam app id
+const APP_ID: &str = "381210";
+
+// Where each kind of Steam keeps its data, relative to the home directory
+const STEAM_ROOTS: &[(&str, Launcher)] = &[
+    (".local/share/Steam", Launcher::Steam),
+    (".steam/steam", Launcher::Steam),
+    (".steam/root", Launcher::Steam),
+    (".var/app/com.valvesoftware.Steam/.local/share/Steam", Launcher::SteamFlatpak),
+    (".var/app/com.valvesoftware.Steam/data/Steam", Launcher::SteamFlatpak),
+    ("snap/steam/common/.local/share/Steam", Launcher::SteamSnap),
+];
+
+// A node of Valve's KeyValues text format (libraryfolders.vdf, appmanifest_*.acf)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vdf {
+    Value(String),
+    Section(Vec<(String, Vdf)>),
+}
+
+impl Vdf {
+    // Keys are matched ignoring case, Steam itself isn't consistent about it
+    pub fn get(&self, key: &str) -> Option<&Vdf> {
+        match self {
+            Vdf::Section(entries) => entries
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value),
+            Vdf::Value(_) => None,
+        }
+    }
+
+    pub fn value(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Vdf::Value(value) => Some(value),
+            Vdf::Section(_) => None,
+        }
+    }
+
+    pub fn entries(&self) -> &[(String, Vdf)] {
+        match self {
+            Vdf::Section(entries) => entries,
+            Vdf::Value(_) => &[],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some(other) => word.push(other),
+                            None => bail!("Unterminated string"),
+                        },
+                        Some(other) => word.push(other),
+                        None => bail!("Unterminated string"),
+                    }
+                }
+                tokens.push(Token::Text(word));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            // Unquoted words are allowed too
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '{' || next == '}' || next == '"' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Text(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+pub fn parse_vdf(text: &str) -> Result<Vdf> {
+    let mut tokens = tokenize(text)?.into_iter();
+    parse_section(&mut tokens, true)
+}
+
+fn parse_section(tokens: &mut impl Iterator<Item = Token>, top_level: bool) -> Result<Vdf> {
+    let mut entries = Vec::new();
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) if !top_level => return Ok(Vdf::Section(entries)),
+            None if top_level => return Ok(Vdf::Section(entries)),
+            Some(token) => bail!("Unexpected {:?}", token),
+            None => bail!("Missing closing brace"),
+        };
+        let value = match tokens.next() {
+            Some(Token::Text(value)) => Vdf::Value(value),
+            Some(Token::Open) => parse_section(tokens, false)?,
+            _ => bail!("Missing value for \"{}\"", key),
+        };
+        entries.push((key, value));
+    }
+}
+
+// Library folders listed in a Steam root, the root itself is always one of them
+fn library_folders(root: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![root.to_path_buf()];
+
+    for file in ["steamapps/libraryfolders.vdf", "config/libraryfolders.vdf"] {
+        let Ok(text) = fs::read_to_string(root.join(file)) else {
+            continue;
+        };
+        let Ok(vdf) = parse_vdf(&text) else {
+            continue;
+        };
+        let Some(folders) = vdf.get("libraryfolders") else {
+            continue;
+        };
+        for (_, folder) in folders.entries() {
+            // Old files list the path directly, newer ones in a section with "path"
+            let path = match folder {
+                Vdf::Value(path) => Some(path.as_str()),
+                Vdf::Section(_) => folder.value("path"),
+            };
+            if let Some(path) = path {
+                libraries.push(PathBuf::from(path));
+            }
+        }
+    }
+
+    libraries
+}
+
+// The game folder named by the app manifest of a library, if the game is installed there. Like
+// the other launchers' installs it must look like a game folder, a broken or partial install
+// would only be rejected once it was picked.
+fn game_in_library(library: &Path) -> Option<PathBuf> {
+    let steamapps = library.join("steamapps");
+    let text = fs::read_to_string(steamapps.join(format!("appmanifest_{}.acf", APP_ID))).ok()?;
+    let manifest = parse_vdf(&text).ok()?;
+    let installdir = manifest.get("AppState")?.value("installdir")?;
+    let path = steamapps.join("common").join(installdir);
+    is_valid_game_folder(&path).then_some(path)
+}
+
+// Every Dead by Daylight install in the Steam libraries found under `home`
+pub fn find_installs(home: &Path) -> Vec<GameInstall> {
+    let mut installs = Vec::new();
+    // Canonical roots and game folders, so links don't list an install twice
+    let mut seen = Vec::new();
+
+    for (root, launcher) in STEAM_ROOTS {
+        let root = home.join(root);
+        let Ok(canonical_root) = root.canonicalize() else {
+            continue;
+        };
+        if seen.contains(&canonical_root) {
+            continue;
+        }
+        seen.push(canonical_root);
+
+        for library in library_folders(&root) {
+            let Some(path) = game_in_library(&library) else {
+                continue;
+            };
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            if seen.contains(&canonical) {
+                continue;
+            }
+            seen.push(canonical);
+            installs.push(GameInstall { path, launcher: *launcher });
+        }
+    }
+
+    installs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY_FOLDERS: &str = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/deck/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"29212173"
+		}
+	}
+	"1"
+	{
+		"path"		"/run/media/mmcblk0p1"
+		"apps"
+		{
+			"381210"		"51354213712"
+		}
+	}
+}
+"#;
+
+    #[test]
+    fn parses_library_folders() {
+        let vdf = parse_vdf(LIBRARY_FOLDERS).unwrap();
+        let folders = vdf.get("libraryfolders").unwrap();
+        let paths: Vec<&str> = folders.entries().iter().filter_map(|(_, folder)| folder.value("path")).collect();
+        assert_eq!(paths, ["/home/deck/.local/share/Steam", "/run/media/mmcblk0p1"]);
+        assert_eq!(folders.get("1").unwrap().get("apps").unwrap().value("381210"), Some("51354213712"));
+    }
+
+    #[test]
+    fn parses_escapes_and_comments() {
+        let vdf = parse_vdf("// comment\n\"AppState\" { \"installdir\" \"Dead \\\"by\\\" Daylight\" \"path\" \"D:\\\\Games\" }").unwrap();
+        let state = vdf.get("appstate").unwrap();
+        assert_eq!(state.value("installdir"), Some("Dead \"by\" Daylight"));
+        assert_eq!(state.value("path"), Some("D:\\Games"));
+        assert!(parse_vdf("\"AppState\" {").is_err());
+        assert!(parse_vdf("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn finds_installs_in_secondary_and_flatpak_libraries() {
+        let home = std::env::temp_dir().join(format!("myc-steam-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+
+        let native = home.join(".local/share/Steam");
+        let card = home.join("sdcard");
+        let flatpak = home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam");
+        let manifest = "\"AppState\"\n{\n\t\"appid\"\t\"381210\"\n\t\"installdir\"\t\"Dead by Daylight\"\n}\n";
+
+        fs::create_dir_all(native.join("steamapps")).unwrap();
+        fs::write(
+            native.join("steamapps/libraryfolders.vdf"),
+            format!("\"libraryfolders\" {{ \"0\" {{ \"path\" \"{}\" }} \"1\" {{ \"path\" \"{}\" }} }}", native.display(), card.display()),
+        )
+        .unwrap();
+        for library in [&native, &card, &flatpak] {
+            fs::create_dir_all(library.join("steamapps")).unwrap();
+            fs::write(library.join("steamapps/appmanifest_381210.acf"), manifest).unwrap();
+        }
+        for library in [&card, &flatpak] {
+            let game = library.join("steamapps/common/Dead by Daylight");
+            fs::create_dir_all(game.join("DeadByDaylight/Content")).unwrap();
+            fs::create_dir_all(game.join("EasyAntiCheat")).unwrap();
+        }
+        // A partial install with only a manifest and an empty folder is skipped
+        fs::create_dir_all(native.join("steamapps/common/Dead by Daylight")).unwrap();
+        // The usual link to the native root must not list its games twice
+        fs::create_dir_all(home.join(".steam")).unwrap();
+        std::os::unix::fs::symlink(&native, home.join(".steam/steam")).unwrap();
+
+        let installs = find_installs(&home);
+        assert_eq!(
+            installs,
+            vec![
+                GameInstall { path: card.join("steamapps/common/Dead by Daylight"), launcher: Launcher::Steam },
+                GameInstall { path: flatpak.join("steamapps/common/Dead by Daylight"), launcher: Launcher::SteamFlatpak },
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&home);
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+const NSSWITCH_CONF: &str = "/etc/nsswitch.conf";
+// The DNS proxy re-reads its rules at most every 2 seconds
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    Blocked,
+    Allowed,
+    // Resolves to the same addresses as the target hostname
+    Redirected { target: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expectation {
+    pub host: String,
+    pub expected: Expected,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub host: String,
+    pub expected: Expected,
+    pub result: Result<Vec<IpAddr>, String>,
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    // Only meaningful for methods that rely on /etc/hosts
+    pub nsswitch_skips_files: bool,
+}
+
+// Resolves through getaddrinfo, so nsswitch, nscd and whatever resolver the system uses all apply
+// exactly like they do for the game.
+fn system_resolve(host: &str) -> Result<Vec<IpAddr>, String> {
+    (host, 443)
+        .to_socket_addrs()
+        .map(|addrs| {
+            let mut ips: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+            ips.dedup();
+            ips
+        })
+        .map_err(|e| e.to_string())
+}
+
+fn is_blocked(addrs: &[IpAddr]) -> bool {
+    !addrs.is_empty() && addrs.iter().all(|addr| addr.is_unspecified())
+}
+
+// Blocks for a few seconds at least, run it off the UI thread.
+pub fn verify(expectations: &[Expectation], uses_hosts_file: bool) -> Report {
+    thread::sleep(SETTLE_TIME);
+
+    // Redirect targets are shared by many hosts, resolve each once
+    let mut targets: HashMap<&str, Result<Vec<IpAddr>, String>> = HashMap::new();
+    let mut checks = Vec::with_capacity(expectations.len());
+
+    for expectation in expectations {
+        let result = system_resolve(&expectation.host);
+        let ok = match (&expectation.expected, &result) {
+            (Expected::Blocked, Ok(addrs)) => is_blocked(addrs),
+            (Expected::Allowed, Ok(addrs)) => !addrs.is_empty() && !is_blocked(addrs),
+            (Expected::Redirected { target }, Ok(addrs)) => {
+                let target_addrs = targets.entry(target.as_str()).or_insert_with(|| system_resolve(target));
+                match target_addrs {
+                    Ok(target_addrs) => !is_blocked(addrs) && addrs.iter().any(|addr| target_addrs.contains(addr)),
+                    Err(_) => false,
+                }
+            }
+            (_, Err(_)) => false,
+        };
+
+        checks.push(Check {
+            host: expectation.host.clone(),
+            expected: expectation.expected.clone(),
+            result,
+            ok,
+        });
+    }
+
+    Report {
+        checks,
+        nsswitch_skips_files: uses_hosts_file && nsswitch_skips_files(),
+    }
+}
+
+// True when the hosts line of nsswitch.conf exists but doesn't list "files"
+fn nsswitch_skips_files() -> bool {
+    let content = match fs::read_to_string(NSSWITCH_CONF) {
+        Ok(content) => content,
+        Err(_) => return false,
+    };
+
+    content.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .find_map(|line| line.trim().strip_prefix("hosts:"))
+        .map(|sources| !sources.split_whitespace().any(|source| source == "files"))
+        .unwrap_or(false)
+}
+
+impl Report {
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|check| !check.ok)
+    }
+
+    // Answers that prove the change isn't in effect, as opposed to lookups that just failed
+    pub fn contradicted(&self) -> bool {
+        self.failures().any(|check| check.result.is_ok())
+    }
+
+    pub fn all_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn describe(&self) -> String {
+        let count = |expected: fn(&Expected) -> bool| {
+            self.checks.iter().filter(|check| check.ok && expected(&check.expected)).count()
+        };
+        let blocked = count(|e| *e == Expected::Blocked);
+        let allowed = count(|e| *e == Expected::Allowed);
+        let redirected = count(|e| matches!(e, Expected::Redirected { .. }));
+
+        let mut text = format!(
+            "Checked {} hostnames through the system resolver: {} blocked, {} allowed and {} redirected as expected.",
+            self.checks.len(),
+            blocked,
+            allowed,
+            redirected
+        );
+
+        let failures: Vec<&Check> = self.failures().collect();
+        if failures.is_empty() {
+            return text;
+        }
+
+        text.push_str(&format!("\n\n{} hostnames did not resolve as expected:\n", failures.len()));
+        for check in &failures {
+            let expected = match &check.expected {
+                Expected::Blocked => "blocked".to_string(),
+                Expected::Allowed => "allowed".to_string(),
+                Expected::Redirected { target } => format!("redirected to {}", target),
+            };
+            let actual = match &check.result {
+                Ok(addrs) => addrs.iter().map(|addr| addr.to_string()).collect::<Vec<_>>().join(", "),
+                Err(e) => format!("lookup failed: {}", e),
+            };
+            text.push_str(&format!("• {} should be {}, got {}\n", check.host, expected, actual));
+        }
+
+        if self.nsswitch_skips_files {
+            text.push_str(&format!(
+                "\nThe hosts line in {} doesn't list \"files\", so /etc/hosts is never consulted.",
+                NSSWITCH_CONF
+            ));
+        } else {
+            text.push_str(
+                "\nSomething between the game and these settings still answers with old or real addresses: \
+                a DNS cache such as nscd that wasn't flushed, DNS-over-HTTPS in the resolver or browser, \
+                or a VPN that brings its own DNS.",
+            );
+        }
+
+        text
+    }
+}
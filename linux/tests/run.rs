@@ -0,0 +1,54 @@
+// `make-your-choice run` installs signal handlers in its own process, so it is tested through
+// the binary instead of from the unit tests that share one process
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+fn home(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("myc-run-{}-{}", name, std::process::id()))
+}
+
+// A config and home directory of its own, so no saved selection or profile is applied
+fn run_command(name: &str, script: &str) -> Command {
+    let home = home(name);
+    std::fs::create_dir_all(&home).unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_make-your-choice"));
+    command
+        .args(["run", "--", "sh", "-c", script])
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+fn cleanup(name: &str) {
+    let _ = std::fs::remove_dir_all(home(name));
+}
+
+#[test]
+fn passes_the_exit_code_on() {
+    let status = run_command("exit", "exit 3").status().unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    // Like a shell: 128 + signal number when the game crashed
+    let status = run_command("crash", "kill -SEGV $$").status().unwrap();
+    assert_eq!(status.code(), Some(128 + libc::SIGSEGV));
+
+    cleanup("exit");
+    cleanup("crash");
+}
+
+#[test]
+fn stopping_the_wrapper_stops_the_game() {
+    let mut wrapper = run_command("stop", "trap 'exit 7' TERM; sleep 5 & wait").spawn().unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(wrapper.id() as i32, libc::SIGTERM);
+    }
+
+    // The game got the signal and the wrapper lived on to pass its exit code
+    assert_eq!(wrapper.wait().unwrap().code(), Some(7));
+    cleanup("stop");
+}